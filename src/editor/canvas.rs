@@ -10,8 +10,8 @@ use std::{
 
 use eframe::{
     egui::{
-        Align, Button, CollapsingHeader, Color32, Layout, Pos2, Rect, RichText, Scene, Stroke,
-        StrokeKind, TextStyle, Tooltip, Ui, UiBuilder, Vec2,
+        Align, Align2, Button, CollapsingHeader, Color32, Layout, Pos2, Rect, RichText, Scene,
+        Stroke, StrokeKind, TextStyle, Tooltip, Ui, UiBuilder, Vec2,
     },
     epaint::{ColorMode, CubicBezierShape, PathStroke},
 };
@@ -21,7 +21,10 @@ use tapestry_weave::{ulid::Ulid, v0::InnerNodeContent};
 
 use crate::{
     editor::{
-        lists::{render_horizontal_node_label_buttons_ltr, render_node_context_menu},
+        lists::{
+            render_cluster_button, render_horizontal_node_label_buttons_ltr,
+            render_node_context_menu,
+        },
         shared::{
             NodeIndex, SharedState,
             layout::{WeaveLayout, wire_bezier_3},
@@ -81,7 +84,7 @@ impl CanvasView {
         state: &mut SharedState,
         _shortcuts: FlagSet<Shortcuts>,
     ) {
-        if state.has_weave_changed || state.has_theme_changed {
+        if state.has_weave_changed || state.has_theme_changed || state.has_clusters_changed {
             self.roots.clear();
         }
     }
@@ -96,7 +99,22 @@ impl CanvasView {
 
         let active = HashSet::new();
 
-        let identifiers = weave.dump_identifiers_ordered_u128_rev();
+        let mut hidden: HashSet<u128> = HashSet::new();
+
+        for id in weave.dump_identifiers_ordered_u128() {
+            if let Some(node) = weave.get_node_u128(&id)
+                && (node.from.is_some_and(|parent| hidden.contains(&parent))
+                    || state.is_hidden_by_cluster(weave, &Ulid(id)))
+            {
+                hidden.insert(id);
+            }
+        }
+
+        let identifiers: Vec<u128> = weave
+            .dump_identifiers_ordered_u128_rev()
+            .into_iter()
+            .filter(|id| !hidden.contains(id))
+            .collect();
 
         let sizes: Vec<_> = identifiers
             .iter()
@@ -143,7 +161,8 @@ impl CanvasView {
         }
 
         self.nodes.clear();
-        self.roots.extend(weave.get_roots());
+        self.roots
+            .extend(weave.get_roots().filter(|root| !hidden.contains(&root.0)));
 
         self.active = weave.get_active_thread().collect();
 
@@ -183,7 +202,13 @@ impl CanvasView {
                     item,
                     CanvasNode {
                         rect,
-                        to: node.to.iter().copied().map(Ulid).collect(),
+                        to: node
+                            .to
+                            .iter()
+                            .copied()
+                            .filter(|child| !hidden.contains(child))
+                            .map(Ulid)
+                            .collect(),
                         to_lines: Vec::with_capacity(node.to.len()),
                         max_x,
                         button_rect,
@@ -764,6 +789,20 @@ fn render_node(
             return;
         }
 
+        let hidden = state.collapsed_cluster_size(&Ulid(node.id));
+
+        if hidden > 0 {
+            let margin = ui.spacing().button_padding;
+
+            ui.painter().text(
+                response.rect.right_top() + Vec2::new(-margin.x, margin.y),
+                Align2::RIGHT_TOP,
+                format!("+{hidden}"),
+                TextStyle::Small.resolve(ui.style()),
+                ui.visuals().weak_text_color(),
+            );
+        }
+
         response.context_menu(|ui| {
             render_node_context_menu(ui, settings, state, weave, &node, true);
         });
@@ -792,6 +831,7 @@ fn render_node(
                 if !node.to.is_empty() {
                     render_collapsing_button(ui, state, &Ulid(node.id));
                 }
+                render_cluster_button(ui, state, &Ulid(node.id));
             });
 
             if let InnerNodeContent::Tokens(tokens) = &node.contents.content
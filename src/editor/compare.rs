@@ -0,0 +1,517 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use eframe::egui::{
+    Align, Button, Color32, ComboBox, Frame, Grid, Layout, RichText, ScrollArea, TextFormat,
+    TextStyle, Ui, WidgetText, text::LayoutJob,
+};
+use egui_notify::Toasts;
+use flagset::FlagSet;
+use tapestry_weave::{
+    ulid::Ulid,
+    v0::{InnerNodeContent, TapestryNode},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    editor::{
+        lists::render_node_context_menu,
+        shared::{
+            NodeIndex, SharedState, calculate_average_entropy, calculate_average_logprob,
+            calculate_confidence, change_color_opacity, get_node_color,
+            render_node_metadata_tooltip, weave::WeaveWrapper,
+        },
+    },
+    listing_margin,
+    settings::{Settings, shortcuts::Shortcuts},
+};
+
+// Comparing every sibling against every other sibling is quadratic, so very long siblings are only compared up to this many words
+const MAX_COMPARED_WORDS: usize = 1024;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareSorting {
+    #[default]
+    Weave,
+    TokenCount,
+    AverageLogprob,
+    Confidence,
+    Entropy,
+    Model,
+}
+
+impl CompareSorting {
+    const ALL: [Self; 6] = [
+        Self::Weave,
+        Self::TokenCount,
+        Self::AverageLogprob,
+        Self::Confidence,
+        Self::Entropy,
+        Self::Model,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Weave => "Weave order",
+            Self::TokenCount => "Token count",
+            Self::AverageLogprob => "Average logprob",
+            Self::Confidence => "Confidence",
+            Self::Entropy => "Entropy",
+            Self::Model => "Model",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CompareColumn {
+    id: Ulid,
+    index: usize,
+    words: Vec<(String, f32)>,
+    token_count: Option<usize>,
+    average_logprob: Option<f32>,
+    confidence: Option<f32>,
+    entropy: Option<f32>,
+    model: Option<String>,
+}
+
+impl CompareColumn {
+    fn compare(&self, other: &Self, sorting: CompareSorting) -> Ordering {
+        fn compare_optional<T: PartialOrd>(a: &Option<T>, b: &Option<T>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+                _ => b.is_some().cmp(&a.is_some()),
+            }
+        }
+
+        match sorting {
+            CompareSorting::Weave => Ordering::Equal,
+            CompareSorting::TokenCount => compare_optional(&self.token_count, &other.token_count),
+            CompareSorting::AverageLogprob => {
+                compare_optional(&self.average_logprob, &other.average_logprob)
+            }
+            CompareSorting::Confidence => compare_optional(&self.confidence, &other.confidence),
+            CompareSorting::Entropy => compare_optional(&self.entropy, &other.entropy),
+            CompareSorting::Model => compare_optional(&self.model, &other.model),
+        }
+        .then(self.index.cmp(&other.index))
+    }
+}
+
+// Matched words of each compared pair, keyed by the hashes of both siblings' contents
+type MatchCache = HashMap<(u64, u64), (Vec<bool>, Vec<bool>)>;
+
+#[derive(Default, Debug)]
+pub struct CompareView {
+    columns: Option<Vec<CompareColumn>>,
+    matches: MatchCache,
+    sorting: CompareSorting,
+    descending: bool,
+}
+
+impl CompareView {
+    /*pub fn reset(&mut self) {
+        self.columns = None;
+    }*/
+    pub fn update(
+        &mut self,
+        _weave: &mut WeaveWrapper,
+        _settings: &Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        if state.has_weave_changed || state.has_cursor_node_changed || state.has_clusters_changed {
+            self.columns = None;
+        }
+    }
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        weave: &mut WeaveWrapper,
+        settings: &mut Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        let old_sorting = (self.sorting, self.descending);
+
+        Frame::new()
+            .outer_margin(listing_margin(ui))
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ComboBox::from_id_salt("compare_sorting")
+                        .selected_text(self.sorting.label())
+                        .show_ui(ui, |ui| {
+                            for sorting in CompareSorting::ALL {
+                                ui.selectable_value(&mut self.sorting, sorting, sorting.label());
+                            }
+                        });
+                    ui.toggle_value(
+                        &mut self.descending,
+                        if self.descending {
+                            "\u{E047}"
+                        } else {
+                            "\u{E04C}"
+                        },
+                    )
+                    .on_hover_text(if self.descending {
+                        "Sorted in descending order"
+                    } else {
+                        "Sorted in ascending order"
+                    });
+                });
+            });
+
+        let is_rebuilt = self.columns.is_none();
+        let columns = self
+            .columns
+            .get_or_insert_with(|| build_columns(weave, state, &mut self.matches));
+
+        if is_rebuilt || (self.sorting, self.descending) != old_sorting {
+            columns.sort_by(|a, b| {
+                let ordering = a.compare(b, self.sorting);
+
+                if self.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+
+        if columns.is_empty() {
+            ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                ui.add_space(ui.style().spacing.menu_spacing);
+                ui.label("This node has no children to compare");
+            });
+            return;
+        }
+
+        let column_width = (ui.available_width() / columns.len() as f32)
+            .max(ui.spacing().tooltip_width * 0.75)
+            - ui.style().spacing.item_spacing.x * 2.0;
+
+        ScrollArea::both()
+            .auto_shrink(false)
+            .animated(false)
+            .show(ui, |ui| {
+                Frame::new()
+                    .outer_margin(listing_margin(ui))
+                    .show(ui, |ui| {
+                        ui.horizontal_top(|ui| {
+                            for column in columns.iter() {
+                                if let Some(node) = weave.get_node(&column.id).cloned() {
+                                    ui.vertical(|ui| {
+                                        ui.set_width(column_width);
+                                        render_column(ui, weave, settings, state, column, &node);
+                                    });
+                                    ui.separator();
+                                }
+                            }
+                        });
+                    });
+            });
+    }
+}
+
+fn build_columns(
+    weave: &WeaveWrapper,
+    state: &SharedState,
+    matches: &mut MatchCache,
+) -> Vec<CompareColumn> {
+    let mut items: Vec<Ulid> = if let Some(cursor_node) = state
+        .get_cursor_node()
+        .into_node()
+        .and_then(|id| weave.get_node(&id))
+    {
+        cursor_node
+            .to
+            .iter()
+            .cloned()
+            .map(Ulid)
+            .filter(|child| !state.is_hidden_by_cluster(weave, child))
+            .collect()
+    } else {
+        weave
+            .get_roots()
+            .filter(|root| !state.is_hidden_by_cluster(weave, root))
+            .collect()
+    };
+    state.group_by_cluster(&mut items);
+
+    let nodes: Vec<&TapestryNode> = items.iter().filter_map(|id| weave.get_node(id)).collect();
+
+    let texts: Vec<String> = nodes
+        .iter()
+        .map(|node| String::from_utf8_lossy(&node.contents.content.as_bytes()).into_owned())
+        .collect();
+    let words: Vec<Vec<&str>> = texts
+        .iter()
+        .map(|text| text.split_word_bounds().collect())
+        .collect();
+
+    let hashes: Vec<u64> = texts
+        .iter()
+        .map(|text| {
+            let mut hasher = DefaultHasher::new();
+            text.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+
+    let differences = calculate_differences(&words, &hashes, matches);
+
+    nodes
+        .into_iter()
+        .zip(words)
+        .zip(differences)
+        .enumerate()
+        .map(|(index, ((node, words), differences))| CompareColumn {
+            id: Ulid(node.id),
+            index,
+            words: words
+                .into_iter()
+                .zip(differences)
+                .map(|(word, difference)| (word.to_string(), difference))
+                .collect(),
+            token_count: match &node.contents.content {
+                InnerNodeContent::Tokens(tokens) => Some(tokens.len()),
+                InnerNodeContent::Snippet(_) => None,
+            },
+            average_logprob: calculate_average_logprob(node),
+            confidence: calculate_confidence(node),
+            entropy: calculate_average_entropy(node),
+            model: node
+                .contents
+                .model
+                .as_ref()
+                .map(|model| model.label.clone()),
+        })
+        .collect()
+}
+
+// For each word, calculates the fraction of siblings which do not contain that word at the same position within their longest common subsequence
+// Pairs whose contents haven't changed since the last call reuse their cached matches, so that unrelated weave changes don't repeat every comparison
+fn calculate_differences(
+    words: &[Vec<&str>],
+    hashes: &[u64],
+    matches: &mut MatchCache,
+) -> Vec<Vec<f32>> {
+    let mut missing: Vec<Vec<usize>> = words.iter().map(|words| vec![0; words.len()]).collect();
+    let mut used = HashSet::with_capacity(words.len() * words.len() / 2);
+
+    for a in 0..words.len() {
+        for b in (a + 1)..words.len() {
+            let key = (hashes[a], hashes[b]);
+            used.insert(key);
+
+            let (a_matched, b_matched) = matches
+                .entry(key)
+                .or_insert_with(|| match_words(&words[a], &words[b]));
+
+            for (count, matched) in missing[a].iter_mut().zip(a_matched.iter()) {
+                if !matched {
+                    *count += 1;
+                }
+            }
+            for (count, matched) in missing[b].iter_mut().zip(b_matched.iter()) {
+                if !matched {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    matches.retain(|key, _| used.contains(key));
+
+    let others = words.len().saturating_sub(1).max(1) as f32;
+
+    missing
+        .into_iter()
+        .zip(words)
+        .map(|(missing, words)| {
+            missing
+                .into_iter()
+                .zip(words.iter())
+                .map(|(count, word)| {
+                    if word.trim().is_empty() {
+                        0.0
+                    } else {
+                        count as f32 / others
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn match_words(a: &[&str], b: &[&str]) -> (Vec<bool>, Vec<bool>) {
+    let mut a_matched: Vec<bool> = (0..a.len()).map(|i| i >= MAX_COMPARED_WORDS).collect();
+    let mut b_matched: Vec<bool> = (0..b.len()).map(|j| j >= MAX_COMPARED_WORDS).collect();
+
+    let a = &a[..a.len().min(MAX_COMPARED_WORDS)];
+    let b = &b[..b.len().min(MAX_COMPARED_WORDS)];
+
+    let width = b.len() + 1;
+    let mut lengths = vec![0_u32; (a.len() + 1) * width];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            a_matched[i] = true;
+            b_matched[j] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    (a_matched, b_matched)
+}
+
+fn render_column(
+    ui: &mut Ui,
+    weave: &mut WeaveWrapper,
+    settings: &mut Settings,
+    state: &mut SharedState,
+    column: &CompareColumn,
+    node: &TapestryNode,
+) {
+    let node_color = get_node_color(node, settings);
+
+    ui.horizontal(|ui| {
+        let activate_button = if node.active {
+            Button::new("\u{E06C} Active").selected(true)
+        } else {
+            Button::new("Activate")
+        };
+
+        if ui
+            .add(activate_button)
+            .on_hover_text("Makes this node part of the active thread, without moving the cursor.")
+            .clicked()
+        {
+            weave.set_node_active_status_u128(&node.id, true);
+        }
+
+        if ui
+            .button("\u{E1DA}")
+            .on_hover_text("Move cursor to node")
+            .clicked()
+        {
+            weave.set_node_active_status_u128(&node.id, true);
+            state.set_cursor_node(NodeIndex::Node(column.id));
+        }
+    });
+
+    Grid::new(("compare_stats", column.id.0))
+        .num_columns(2)
+        .show(ui, |ui| {
+            let format_stat = |value: Option<f32>| match value {
+                Some(value) => format!("{value:.2}"),
+                None => "-".to_string(),
+            };
+
+            ui.label("Tokens:");
+            ui.label(
+                column
+                    .token_count
+                    .map(|count| count.to_string())
+                    .unwrap_or("-".to_string()),
+            );
+            ui.end_row();
+
+            ui.label("Avg. logprob:")
+                .on_hover_text("The average log probability of the node's tokens.");
+            ui.label(format_stat(column.average_logprob));
+            ui.end_row();
+
+            ui.label("Confidence:")
+                .on_hover_text("The negated average logprob of the top tokens at each position, as calculated when the node was generated. Higher values indicate that the model was more certain of its choices.");
+            ui.label(format_stat(column.confidence));
+            ui.end_row();
+
+            ui.label("Entropy:")
+                .on_hover_text("The average entropy of each token's distribution (in nats), estimated using the top tokens returned by the model. Higher values indicate that the model was less certain of its choices.");
+            ui.label(format_stat(column.entropy));
+            ui.end_row();
+
+            ui.label("Model:");
+            match (&column.model, node_color) {
+                (Some(model), Some(color)) => ui.colored_label(color, model),
+                (Some(model), None) => ui.label(model),
+                (None, _) => ui.label("-"),
+            };
+            ui.end_row();
+        });
+
+    ui.separator();
+
+    let font_id = TextStyle::Monospace.resolve(ui.style());
+    let text_color = node_color.unwrap_or(ui.visuals().widgets.inactive.text_color());
+    let highlight_color = ui.visuals().selection.bg_fill;
+
+    let mut job = LayoutJob::default();
+
+    for (word, difference) in &column.words {
+        job.append(
+            word,
+            0.0,
+            TextFormat {
+                font_id: font_id.clone(),
+                color: text_color,
+                background: if *difference > 0.0 {
+                    change_color_opacity(highlight_color, 0.2 + (difference * 0.6))
+                } else {
+                    Color32::TRANSPARENT
+                },
+                valign: ui.text_valign(),
+                ..Default::default()
+            },
+        );
+    }
+
+    if job.text.is_empty() {
+        ui.label(RichText::new("No text").weak());
+        return;
+    }
+
+    let response = ui
+        .add(
+            Button::new(WidgetText::LayoutJob(Arc::new(job)))
+                .fill(Color32::TRANSPARENT)
+                .selected(node.active)
+                .wrap(),
+        )
+        .on_hover_ui(|ui| render_node_metadata_tooltip(ui, node));
+
+    if response.contains_pointer() {
+        state.set_hovered_node(NodeIndex::Node(column.id));
+    }
+
+    if response.clicked() {
+        weave.set_node_active_status_u128(&node.id, true);
+    }
+
+    response.context_menu(|ui| {
+        render_node_context_menu(ui, settings, state, weave, node, false);
+    });
+}
@@ -8,9 +8,9 @@ use std::{
 };
 
 use eframe::egui::{
-    Align, Button, Color32, FontFamily, Frame, Id, Layout, Pos2, Rect, RichText, ScrollArea, Sense,
-    Ui, UiBuilder, Vec2, WidgetText, collapsing_header::CollapsingState,
-    scroll_area::ScrollBarVisibility, vec2,
+    Align, Button, Color32, FontFamily, Frame, Id, Key, Layout, Pos2, Rect, RichText, ScrollArea,
+    Sense, Spinner, TextEdit, Ui, UiBuilder, Vec2, Widget, WidgetText,
+    collapsing_header::CollapsingState, scroll_area::ScrollBarVisibility, vec2,
 };
 use egui_notify::Toasts;
 use egui_virtual_list::VirtualList;
@@ -26,12 +26,16 @@ use tapestry_weave::{
 
 use crate::{
     editor::shared::{
-        INSTANT_SCROLL, NodeIndex, SharedState, change_color_opacity, get_node_color,
+        INSTANT_SCROLL, NodeIndex, SearchScope, SharedState, change_color_opacity, get_node_color,
         render_node_metadata_tooltip, render_node_text_or_empty, render_token_tooltip,
         weave::WeaveWrapper,
     },
     listing_margin,
-    settings::{Settings, shortcuts::Shortcuts},
+    settings::{
+        Settings,
+        inference::{ClusterKind, GenerationRecord, PassageKind, SearchResult},
+        shortcuts::Shortcuts,
+    },
 };
 
 #[derive(Debug)]
@@ -60,7 +64,7 @@ impl ListView {
         state: &mut SharedState,
         _shortcuts: FlagSet<Shortcuts>,
     ) {
-        if state.has_weave_changed || state.has_cursor_node_changed {
+        if state.has_weave_changed || state.has_cursor_node_changed || state.has_clusters_changed {
             self.list.reset();
         }
     }
@@ -73,15 +77,25 @@ impl ListView {
         state: &mut SharedState,
         _shortcuts: FlagSet<Shortcuts>,
     ) {
-        let items: Vec<Ulid> = if let Some(cursor_node) = state
+        let mut items: Vec<Ulid> = if let Some(cursor_node) = state
             .get_cursor_node()
             .into_node()
             .and_then(|id| weave.get_node(&id))
         {
-            cursor_node.to.iter().cloned().map(Ulid).collect()
+            cursor_node
+                .to
+                .iter()
+                .cloned()
+                .map(Ulid)
+                .filter(|child| !state.is_hidden_by_cluster(weave, child))
+                .collect()
         } else {
-            weave.get_roots().collect()
+            weave
+                .get_roots()
+                .filter(|root| !state.is_hidden_by_cluster(weave, root))
+                .collect()
         };
+        state.group_by_cluster(&mut items);
 
         let contains_cursor = ui
             .clip_rect()
@@ -151,6 +165,7 @@ impl ListView {
                     &node,
                     |ui, settings, state, weave, node| {
                         render_horizontal_node_label_buttons_rtl(ui, settings, state, weave, node);
+                        render_cluster_button(ui, state, &Ulid(node.id));
                     },
                     |ui, settings, state, weave, node| {
                         render_node_context_menu(ui, settings, state, weave, node, false);
@@ -298,6 +313,219 @@ impl BookmarkListView {
     }
 }
 
+#[derive(Debug)]
+pub struct SearchListView {
+    list: VirtualList,
+}
+
+impl Default for SearchListView {
+    fn default() -> Self {
+        let mut list = VirtualList::new();
+        list.scroll_position_sync_on_resize(false);
+
+        Self { list }
+    }
+}
+
+const SEARCH_PREVIEW_LENGTH: usize = 160;
+
+impl SearchListView {
+    pub fn update(
+        &mut self,
+        _weave: &mut WeaveWrapper,
+        _settings: &Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        if state.has_weave_changed || state.has_search_changed {
+            self.list.reset();
+        }
+    }
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        weave: &mut WeaveWrapper,
+        settings: &mut Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        Frame::new()
+            .outer_margin(listing_margin(ui))
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let query_response = TextEdit::singleline(&mut state.search_query)
+                        .hint_text("Search passages")
+                        .ui(ui);
+                    let submitted = query_response.lost_focus()
+                        && ui.input(|input| input.key_pressed(Key::Enter));
+
+                    ui.selectable_value(&mut state.search_scope, SearchScope::Weave, "This weave");
+                    ui.selectable_value(
+                        &mut state.search_scope,
+                        SearchScope::Documents,
+                        "All weaves",
+                    )
+                    .on_hover_text("Searches every weave within the document folder. Other open weaves are searched as of their last save.");
+
+                    if state.is_searching() {
+                        ui.add(Spinner::new());
+                    } else if ui.button("\u{E151}").on_hover_text("Search").clicked() || submitted
+                    {
+                        state.search(weave, settings, None);
+                    }
+                });
+            });
+
+        let item_count = state.get_search_results().len();
+
+        let contains_cursor = ui
+            .clip_rect()
+            .contains(ui.ctx().pointer_hover_pos().unwrap_or_default());
+
+        ScrollArea::vertical()
+            .auto_shrink(false)
+            .animated(false)
+            .show(ui, |ui| {
+                Frame::new()
+                    .outer_margin(listing_margin(ui))
+                    .show(ui, |ui| {
+                        let max_autoscroll_height = ui.available_size_before_wrap().y;
+
+                        if settings.interface.auto_scroll {
+                            for index in 0..item_count {
+                                let result = state.get_search_results()[index].clone();
+
+                                Self::render_result(
+                                    weave,
+                                    settings,
+                                    state,
+                                    ui,
+                                    &result,
+                                    index == 0,
+                                    contains_cursor,
+                                    max_autoscroll_height,
+                                );
+                            }
+                        } else {
+                            self.list.ui_custom_layout(ui, item_count, |ui, index| {
+                                if let Some(result) = state.get_search_results().get(index).cloned()
+                                {
+                                    Self::render_result(
+                                        weave,
+                                        settings,
+                                        state,
+                                        ui,
+                                        &result,
+                                        index == 0,
+                                        contains_cursor,
+                                        max_autoscroll_height,
+                                    );
+                                }
+                                1
+                            });
+                        }
+                    });
+            });
+    }
+    fn render_result(
+        weave: &mut WeaveWrapper,
+        settings: &mut Settings,
+        state: &mut SharedState,
+        ui: &mut Ui,
+        result: &SearchResult,
+        is_start: bool,
+        contains_cursor: bool,
+        max_autoscroll_height: f32,
+    ) {
+        if let Some(path) = &result.passage.path {
+            if !is_start {
+                render_label_separator(ui, settings);
+            }
+            ui.horizontal_wrapped(|ui| {
+                ui.add_space(ui.spacing().icon_spacing);
+                render_search_result_icon(ui, result);
+
+                let text = String::from_utf8_lossy(&result.passage.content);
+                let text = text.trim();
+                let preview: String = match result.passage.kind {
+                    PassageKind::Node => text.chars().take(SEARCH_PREVIEW_LENGTH).collect(),
+                    PassageKind::Thread => {
+                        let mut preview: Vec<char> =
+                            text.chars().rev().take(SEARCH_PREVIEW_LENGTH).collect();
+                        preview.reverse();
+                        preview.into_iter().collect()
+                    }
+                };
+                let title = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy())
+                    .unwrap_or_default();
+
+                let response = ui
+                    .add(
+                        Button::new(format!("{title}: {preview}"))
+                            .frame(false)
+                            .fill(Color32::TRANSPARENT)
+                            .wrap(),
+                    )
+                    .on_hover_text(path.to_string_lossy());
+
+                if response.clicked() {
+                    state.open_weave(path.as_ref().clone());
+                }
+
+                response.context_menu(|ui| {
+                    if ui.button("Open weave").clicked() {
+                        state.open_weave(path.as_ref().clone());
+                    }
+                    if ui.button("Copy text").clicked() {
+                        ui.ctx().copy_text(text.to_string());
+                    }
+                });
+            });
+        } else if let Some(node) = weave.get_node(&result.passage.node).cloned() {
+            if !is_start {
+                render_label_separator(ui, settings);
+            }
+            ui.horizontal_wrapped(|ui| {
+                ui.add_space(ui.spacing().icon_spacing);
+                render_search_result_icon(ui, result);
+
+                render_horizontal_node_label(
+                    ui,
+                    settings,
+                    state,
+                    weave,
+                    &node,
+                    |ui, settings, state, weave, node| {
+                        render_horizontal_node_label_buttons_rtl(ui, settings, state, weave, node);
+                    },
+                    |ui, settings, state, weave, node| {
+                        render_node_context_menu(ui, settings, state, weave, node, false);
+                    },
+                    true,
+                    contains_cursor,
+                    max_autoscroll_height,
+                );
+            });
+        }
+    }
+}
+
+fn render_search_result_icon(ui: &mut Ui, result: &SearchResult) {
+    match result.passage.kind {
+        PassageKind::Node => ui
+            .label("\u{E151}")
+            .on_hover_text(format!("Node (similarity: {:.3})", result.similarity)),
+        PassageKind::Thread => ui.label("\u{E408}").on_hover_text(format!(
+            "Thread ending at this node (similarity: {:.3})",
+            result.similarity
+        )),
+    };
+}
+
 #[derive(Debug)]
 pub struct TreeListView {
     last_active_nodes: HashSet<Ulid>,
@@ -340,6 +568,7 @@ impl TreeListView {
         } else if state.has_weave_changed
             || self.last_max_depth != settings.interface.max_tree_depth
             || state.has_opened_changed
+            || state.has_clusters_changed
         {
             self.needs_list_refresh = true;
         }
@@ -412,7 +641,7 @@ impl TreeListView {
             self.update_lists(settings.interface.max_tree_depth);
         }
 
-        let tree_roots: Vec<Ulid> = if let Some(cursor_node) = state
+        let mut tree_roots: Vec<Ulid> = if let Some(cursor_node) = state
             .get_cursor_node()
             .into_node()
             .and_then(|id| weave.get_node(&id))
@@ -426,8 +655,12 @@ impl TreeListView {
                 vec![Ulid(cursor_node_parent_parent)]
             }
         } else {
-            weave.get_roots().collect()
+            weave
+                .get_roots()
+                .filter(|root| !state.is_hidden_by_cluster(weave, root))
+                .collect()
         };
+        state.group_by_cluster(&mut tree_roots);
 
         self.last_rendered_nodes.clear();
 
@@ -606,6 +839,7 @@ fn render_node_tree_row(
                     &node,
                     |ui, settings, state, weave, node| {
                         render_horizontal_node_label_buttons_rtl(ui, settings, state, weave, node);
+                        render_cluster_button(ui, state, &Ulid(node.id));
                         if is_display_root
                             && let Some(parent) = node.from
                             && ui
@@ -635,6 +869,15 @@ fn render_node_tree_row(
                 })
                 .body(|ui| {
                     if max_depth > 0 {
+                        let mut children: Vec<Ulid> = node
+                            .to
+                            .iter()
+                            .copied()
+                            .map(Ulid)
+                            .filter(|child| !state.is_hidden_by_cluster(weave, child))
+                            .collect();
+                        state.group_by_cluster(&mut children);
+
                         render_node_tree(
                             weave,
                             settings,
@@ -642,7 +885,7 @@ fn render_node_tree_row(
                             ui,
                             editor_id,
                             item,
-                            node.to.into_iter().map(Ulid),
+                            children.into_iter(),
                             max_depth - 1,
                             within_virtual_list,
                             rendered_items,
@@ -680,6 +923,38 @@ fn render_node_tree_row(
     }
 }
 
+pub fn render_cluster_button(ui: &mut Ui, state: &mut SharedState, node: &Ulid) {
+    for kind in ClusterKind::ALL {
+        if let Some(cluster) = state.get_cluster(kind, node)
+            && cluster.members[0] == *node
+        {
+            let hidden = cluster.members.len() - 1;
+
+            if state.is_cluster_collapsed(kind, node) {
+                if ui
+                    .button(format!("\u{E464} +{hidden}"))
+                    .on_hover_text(match kind {
+                        ClusterKind::Semantic => "Show similar siblings",
+                        ClusterKind::Duplicate => "Show duplicate siblings",
+                    })
+                    .clicked()
+                {
+                    state.toggle_cluster(kind, *node);
+                }
+            } else if ui
+                .button("\u{E467}")
+                .on_hover_text(match kind {
+                    ClusterKind::Semantic => "Hide similar siblings",
+                    ClusterKind::Duplicate => "Hide duplicate siblings",
+                })
+                .clicked()
+            {
+                state.toggle_cluster(kind, *node);
+            }
+        }
+    }
+}
+
 pub fn render_horizontal_node_label_buttons_ltr(
     ui: &mut Ui,
     settings: &Settings,
@@ -1198,6 +1473,54 @@ pub fn render_node_context_menu(
         state.set_open(Ulid(node.id), true);
     }
 
+    if ui
+        .button("Score thread")
+        .on_hover_text("Requests prompt logprobs for the thread up to this node, converting unscored text into tokens.")
+        .clicked()
+    {
+        state.score_thread(weave, Ulid(node.id), settings);
+    }
+
+    if node
+        .contents
+        .metadata
+        .contains_key(GenerationRecord::METADATA_KEY)
+    {
+        if ui
+            .button("Regenerate siblings like this")
+            .on_hover_text("Generates new siblings of this node using the model and request parameters which produced it, keeping the current recursion depth and context settings.")
+            .clicked()
+        {
+            state.regenerate_like(weave, Ulid(node.id), settings);
+        }
+
+        if ui
+            .button("Copy parameters to current preset")
+            .on_hover_text("Replaces the request parameters of this node's model in the editor's inference parameters with the ones which produced this node, adding the model if it isn't already present.")
+            .clicked()
+        {
+            state.copy_generation_parameters(node);
+        }
+    }
+
+    if state.get_capture(&Ulid(node.id)).is_some() {
+        if ui
+            .button("Inspect request")
+            .on_hover_text("Shows the raw request and response which produced this node.")
+            .clicked()
+        {
+            state.inspect_capture(Ulid(node.id));
+        }
+
+        if ui
+            .button("Re-run request")
+            .on_hover_text("Sends the exact request which produced this node again, adding the results as siblings of this node.")
+            .clicked()
+        {
+            state.replay_capture(weave, Ulid(node.id), settings);
+        }
+    }
+
     let bookmark_label = if node.bookmarked {
         "Remove bookmark"
     } else {
@@ -1207,6 +1530,14 @@ pub fn render_node_context_menu(
         weave.set_node_bookmarked_status_u128(&node.id, !node.bookmarked);
     }
 
+    if ui
+        .button("Find similar passages")
+        .on_hover_text("Searches for nodes and threads which are semantically similar to this node's text, using the embedding model.")
+        .clicked()
+    {
+        state.find_similar(weave, Ulid(node.id), settings);
+    }
+
     ui.separator();
 
     let add_child_response = ui.button(if !is_modifier_pressed {
@@ -1295,6 +1626,27 @@ pub fn render_node_context_menu(
             state.seriate_children(weave, Some(Ulid(node.id)), settings);
         }
 
+        if ui
+            .button("Cluster children")
+            .on_hover_text("Groups semantically similar children using the embedding model. Each group can be collapsed into its first member.")
+            .clicked()
+        {
+            state.cluster_children(weave, Some(Ulid(node.id)), settings);
+        }
+
+        if ui
+            .button("Group duplicate children")
+            .on_hover_text("Groups near-duplicate children, using the similarity metric chosen in the inference settings. Each group can be collapsed into its first member.")
+            .clicked()
+        {
+            state.find_duplicates(weave, Some(Ulid(node.id)), settings);
+        }
+
+        if state.has_clusters(weave, Some(Ulid(node.id))) && ui.button("Ungroup children").clicked()
+        {
+            state.clear_clusters(weave, Some(Ulid(node.id)));
+        }
+
         if ui.button("Sort children by confidence").clicked() {
             state.sort_children_by_confidence(weave, Some(Ulid(node.id)));
         }
@@ -1312,6 +1664,21 @@ pub fn render_node_context_menu(
         }
     }
 
+    let deletable = state.deletable_duplicates(weave, &Ulid(node.id)).len();
+
+    if let Some(cluster) = state.get_cluster(ClusterKind::Duplicate, &Ulid(node.id))
+        && ui
+            .add_enabled(deletable > 0, Button::new("Keep this, delete duplicates"))
+            .on_hover_text(format!(
+                "Deletes the {deletable} of the {} other nodes grouped with this node as duplicates which have no children and aren't part of the active thread. Duplicates with children or on the active thread are kept, as deleting them would also delete their descendants.",
+                cluster.members.len() - 1
+            ))
+            .on_disabled_hover_text("All other duplicates in this group have children or are part of the active thread, so none of them can be deleted.")
+            .clicked()
+    {
+        state.delete_cluster_duplicates(weave, Ulid(node.id));
+    }
+
     if ui.button("Delete all siblings").clicked() {
         let siblings: Vec<Ulid> =
             if let Some(parent) = node.from.and_then(|id| weave.get_node_u128(&id)) {
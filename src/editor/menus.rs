@@ -34,7 +34,7 @@ impl MenuView {
                 Frame::new()
                     .outer_margin(ui.style().spacing.menu_margin)
                     .show(ui, |ui| {
-                        state.inference.render(&settings.inference, ui);
+                        state.render_inference_parameters(settings, ui);
                     });
             });
     }
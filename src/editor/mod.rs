@@ -14,17 +14,19 @@ use std::{
 };
 
 mod canvas;
+mod compare;
 mod graph;
 mod lists;
 mod menus;
 mod shared;
 mod textedit;
+mod timeline;
 
 // TODO: Implement node search
 
 use eframe::egui::{
-    Align, Context, Key, Layout, Modal, OutputCommand, Sides, Spinner, TopBottomPanel, Ui,
-    WidgetText,
+    Align, Context, Id, Key, Layout, Modal, OutputCommand, Sides, Spinner, TopBottomPanel, Ui,
+    WidgetText, Window,
 };
 use egui_notify::Toasts;
 use egui_tiles::{
@@ -42,13 +44,19 @@ use tokio::runtime::Runtime;
 use crate::{
     editor::{
         canvas::CanvasView,
+        compare::CompareView,
         graph::GraphView,
-        lists::{BookmarkListView, ListView, TreeListView},
+        lists::{BookmarkListView, ListView, SearchListView, TreeListView},
         menus::{InfoView, MenuView},
-        shared::{SharedState, weave::WeaveWrapper},
+        shared::{SharedState, render_request_capture, weave::WeaveWrapper},
         textedit::TextEditorView,
+        timeline::TimelineView,
+    },
+    settings::{
+        Settings,
+        inference::{InferenceCache, InferenceClient},
+        shortcuts::Shortcuts,
     },
-    settings::{Settings, inference::InferenceClient, shortcuts::Shortcuts},
 };
 
 pub struct Editor {
@@ -82,6 +90,7 @@ impl Editor {
         open_documents: Rc<RefCell<HashSet<PathBuf>>>,
         runtime: Arc<Runtime>,
         client: Rc<RefCell<Option<InferenceClient>>>,
+        cache: InferenceCache,
         path: Option<PathBuf>,
         new_path_callback: Box<dyn FnMut(&PathBuf)>,
     ) -> Self {
@@ -101,7 +110,9 @@ impl Editor {
             tiles.insert_pane(Pane::Graph),
             tiles.insert_pane(Pane::TreeList),
             tiles.insert_pane(Pane::List),
+            tiles.insert_pane(Pane::Compare),
             tiles.insert_pane(Pane::BookmarkList),
+            tiles.insert_pane(Pane::Search),
         ];
         let active_left_tab = left_tabs[2];
 
@@ -117,6 +128,7 @@ impl Editor {
                 tiles.insert_pane(Pane::TextEdit),
                 tiles.insert_pane(Pane::Menu),
                 tiles.insert_pane(Pane::Info),
+                tiles.insert_pane(Pane::Timeline),
             ];
 
             tiles.insert_tab_tile(right_tabs)
@@ -126,7 +138,10 @@ impl Editor {
                 tiles.insert_pane(Pane::Info),
             ];
 
-            let right_lower_tabs = vec![tiles.insert_pane(Pane::Menu)];
+            let right_lower_tabs = vec![
+                tiles.insert_pane(Pane::Menu),
+                tiles.insert_pane(Pane::Timeline),
+            ];
 
             let right_upper_tab_tile = tiles.insert_tab_tile(right_upper_tabs);
             let right_lower_tab_tile = tiles.insert_tab_tile(right_lower_tabs);
@@ -142,7 +157,9 @@ impl Editor {
 
         let weave = Arc::new(Mutex::new(None));
 
-        let shared_state = SharedState::new(identifier, runtime, client, &settings.borrow());
+        let mut shared_state =
+            SharedState::new(identifier, runtime, client, cache, &settings.borrow());
+        shared_state.set_path(path.clone());
 
         Self {
             settings: settings.clone(),
@@ -173,8 +190,11 @@ impl Editor {
                 graph_view: GraphView::default(),
                 tree_list_view: TreeListView::default(),
                 list_view: ListView::default(),
+                compare_view: CompareView::default(),
                 bookmark_list_view: BookmarkListView::default(),
+                search_list_view: SearchListView::default(),
                 text_edit_view: TextEditorView::default(),
+                timeline_view: TimelineView::default(),
                 menu_view: MenuView::default(),
                 info_view: InfoView::default(),
                 shortcuts: FlagSet::default(),
@@ -189,6 +209,7 @@ impl Editor {
         &mut self,
         ui: &mut Ui,
         mut close_callback: impl FnMut(),
+        mut open_callback: impl FnMut(PathBuf),
         shortcuts: FlagSet<Shortcuts>,
     ) {
         self.behavior.shortcuts = shortcuts;
@@ -237,6 +258,10 @@ impl Editor {
                     drop(weave);
                     self.render_weave(ui);
 
+                    for path in self.behavior.shared_state.take_open_requests() {
+                        open_callback(path);
+                    }
+
                     let mut toasts = self.toasts.borrow_mut();
                     while let Ok(message) = self.error_channel.1.try_recv() {
                         toasts.error(message);
@@ -336,6 +361,7 @@ impl Editor {
                 (self.new_path_callback)(path);
             }
             self.old_path = path.clone();
+            self.behavior.shared_state.set_path(path.clone());
             //self.last_filesize.store(0, Ordering::SeqCst);
             //self.behavior.reset();
         }
@@ -380,6 +406,13 @@ impl Editor {
 
         self.tree.ui(&mut self.behavior, ui);
 
+        self.behavior.render_windows(ui.ctx());
+
+        if self.behavior.shared_state.take_search_focus() {
+            self.tree
+                .make_active(|_, tile| matches!(tile, Tile::Pane(Pane::Search)));
+        }
+
         let settings = self.settings.borrow();
 
         if self.show_modal
@@ -577,8 +610,11 @@ enum Pane {
     Graph,
     TreeList,
     List,
+    Compare,
     BookmarkList,
+    Search,
     TextEdit,
+    Timeline,
     Menu,
     Info,
 }
@@ -589,8 +625,11 @@ struct EditorTilingBehavior {
     graph_view: GraphView,
     tree_list_view: TreeListView,
     list_view: ListView,
+    compare_view: CompareView,
     bookmark_list_view: BookmarkListView,
+    search_list_view: SearchListView,
     text_edit_view: TextEditorView,
+    timeline_view: TimelineView,
     menu_view: MenuView,
     info_view: InfoView,
     settings: Rc<RefCell<Settings>>,
@@ -612,6 +651,41 @@ impl EditorTilingBehavior {
         self.text_edit_view.reset();
         self.menu_view.reset();
     }*/
+    fn render_windows(&mut self, ctx: &Context) {
+        if let Some((node, capture)) = self.shared_state.get_inspected_capture() {
+            let mut open = true;
+            let mut replay = false;
+            let has_node = self
+                .weave
+                .lock()
+                .as_ref()
+                .is_some_and(|weave| weave.get_node(&node).is_some());
+
+            Window::new("Request inspector")
+                .id(Id::new([self.shared_state.identifier.0, 1]))
+                .open(&mut open)
+                .default_width(480.0)
+                .show(ctx, |ui| {
+                    render_request_capture(ui, &capture);
+                    if has_node {
+                        ui.separator();
+                        replay = ui
+                            .button("Re-run request")
+                            .on_hover_text("Sends this exact request again, adding the results as siblings of the node which it produced.")
+                            .clicked();
+                    }
+                });
+
+            if replay && let Some(weave) = self.weave.lock().as_ref() {
+                self.shared_state
+                    .replay_capture(weave, node, &self.settings.borrow());
+            }
+
+            if !open {
+                self.shared_state.close_capture_inspector();
+            }
+        }
+    }
     fn panel_rtl(&mut self, ui: &mut Ui, file_size: usize) {
         let mut weave = self.weave.lock();
 
@@ -648,6 +722,13 @@ impl EditorTilingBehavior {
                 &mut self.shared_state,
                 self.shortcuts,
             );
+            self.compare_view.update(
+                weave,
+                &settings,
+                &mut toasts,
+                &mut self.shared_state,
+                self.shortcuts,
+            );
             self.bookmark_list_view.update(
                 weave,
                 &settings,
@@ -655,6 +736,13 @@ impl EditorTilingBehavior {
                 &mut self.shared_state,
                 self.shortcuts,
             );
+            self.search_list_view.update(
+                weave,
+                &settings,
+                &mut toasts,
+                &mut self.shared_state,
+                self.shortcuts,
+            );
             self.text_edit_view.update(
                 weave,
                 &settings,
@@ -662,6 +750,13 @@ impl EditorTilingBehavior {
                 &mut self.shared_state,
                 self.shortcuts,
             );
+            self.timeline_view.update(
+                weave,
+                &settings,
+                &mut toasts,
+                &mut self.shared_state,
+                self.shortcuts,
+            );
             self.info_view.update(
                 weave,
                 &settings,
@@ -723,6 +818,14 @@ impl Behavior<Pane> for EditorTilingBehavior {
                     &mut self.shared_state,
                     self.shortcuts,
                 ),
+                Pane::Compare => self.compare_view.render(
+                    ui,
+                    weave,
+                    &mut settings,
+                    &mut toasts,
+                    &mut self.shared_state,
+                    self.shortcuts,
+                ),
                 Pane::BookmarkList => self.bookmark_list_view.render(
                     ui,
                     weave,
@@ -731,10 +834,26 @@ impl Behavior<Pane> for EditorTilingBehavior {
                     &mut self.shared_state,
                     self.shortcuts,
                 ),
+                Pane::Search => self.search_list_view.render(
+                    ui,
+                    weave,
+                    &mut settings,
+                    &mut toasts,
+                    &mut self.shared_state,
+                    self.shortcuts,
+                ),
                 Pane::TextEdit => self.text_edit_view.render(
                     ui,
                     weave,
-                    &settings,
+                    &mut settings,
+                    &mut toasts,
+                    &mut self.shared_state,
+                    self.shortcuts,
+                ),
+                Pane::Timeline => self.timeline_view.render(
+                    ui,
+                    weave,
+                    &mut settings,
                     &mut toasts,
                     &mut self.shared_state,
                     self.shortcuts,
@@ -766,8 +885,11 @@ impl Behavior<Pane> for EditorTilingBehavior {
             Pane::Graph => WidgetText::Text("\u{E52E} Graph".to_string()),
             Pane::TreeList => WidgetText::Text("\u{E408} Tree".to_string()),
             Pane::List => WidgetText::Text("\u{E106} List".to_string()),
+            Pane::Compare => WidgetText::Text("\u{E359} Compare".to_string()),
             Pane::BookmarkList => WidgetText::Text("\u{E060} Bookmarks".to_string()),
+            Pane::Search => WidgetText::Text("\u{E151} Search".to_string()),
             Pane::TextEdit => WidgetText::Text("\u{E265} Editor".to_string()),
+            Pane::Timeline => WidgetText::Text("\u{E2A5} Timeline".to_string()),
             Pane::Menu => WidgetText::Text("\u{E1B1} Menu".to_string()),
             Pane::Info => WidgetText::Text("\u{E0F9} Info".to_string()),
         }
@@ -1,18 +1,31 @@
 use std::{
-    borrow::Cow, cell::RefCell, cmp::Ordering, collections::HashMap, hash::BuildHasherDefault,
-    ops::Range, rc::Rc, sync::Arc, time::SystemTime,
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    hash::BuildHasherDefault,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::SystemTime,
 };
 
 use chrono::{DateTime, offset};
 use eframe::egui::{
-    Color32, Context, Rangef, Rgba, RichText, ScrollArea, TextFormat, TextStyle, Ui,
+    CollapsingHeader, Color32, Context, Rangef, Rgba, RichText, ScrollArea, TextEdit, TextFormat,
+    TextStyle, Ui, Widget,
     style::ScrollAnimation,
     text::{LayoutJob, LayoutSection},
 };
 use egui_notify::Toasts;
 use flagset::FlagSet;
+use linked_hash_map::LinkedHashMap;
 use log::{debug, warn};
 use tapestry_weave::{
+    VERSIONED_WEAVE_FILE_EXTENSION, VersionedWeave,
     hashers::UlidHasher,
     ulid::Ulid,
     universal_weave::{
@@ -25,14 +38,18 @@ use tapestry_weave::{
     },
 };
 use tokio::runtime::Runtime;
+use walkdir::WalkDir;
 
 use crate::{
     editor::shared::weave::WeaveWrapper,
     settings::{
         NodeSorting, Settings, UISettings,
         inference::{
-            InferenceCache, InferenceClient, InferenceHandle, InferenceParameters,
-            InferenceSettings, SeriationInferenceHandle, SeriationResponse, TokensOrBytes,
+            CapturedError, ClusterKind, ClusteringInferenceHandle, ClusteringResponse,
+            GenerationRecord, InferenceCache, InferenceClient, InferenceHandle,
+            InferenceParameters, InferenceSettings, Passage, PassageKind, RequestCapture,
+            ScoredNode, ScoringInferenceHandle, SearchIndex, SearchInferenceHandle, SearchResult,
+            SegmentSource, SeriationInferenceHandle, SeriationResponse, TokensOrBytes,
         },
         shortcuts::Shortcuts,
     },
@@ -68,13 +85,49 @@ pub struct SharedState {
     pub has_opened_changed: bool,
     requests: HashMap<Ulid, InferenceHandle>,
     responses: Vec<Result<TapestryNode, anyhow::Error>>,
+    new_captures: Vec<(Ulid, Arc<RequestCapture>)>,
+    captures: LinkedHashMap<Ulid, Arc<RequestCapture>>,
+    inspected_capture: Option<Ulid>,
     seriation_requests: HashMap<Option<Ulid>, SeriationInferenceHandle>,
     seriation_responses: Vec<Result<SeriationResponse, anyhow::Error>>,
+    scoring_requests: HashMap<Ulid, ScoringInferenceHandle>,
+    scoring_responses: Vec<Result<Vec<ScoredNode>, anyhow::Error>>,
+    clustering_requests: HashMap<(ClusterKind, Option<Ulid>), ClusteringInferenceHandle>,
+    clustering_responses: Vec<Result<ClusteringResponse, anyhow::Error>>,
+    clusters: HashMap<(ClusterKind, Ulid), Arc<Cluster>>,
+    collapsed_clusters: HashSet<(ClusterKind, Ulid)>,
+    search_request: Option<SearchInferenceHandle>,
+    search_index: SearchIndex,
+    search_results: Vec<SearchResult>,
+    pub search_query: String,
+    pub search_scope: SearchScope,
+    pub has_search_changed: bool,
+    pub has_clusters_changed: bool,
+    next_clusters_updated: bool,
+    focus_search: bool,
+    path: Option<PathBuf>,
+    open_requests: Vec<PathBuf>,
     last_ui_settings: UISettings,
     pub has_theme_changed: bool,
     last_activated_hovered: bool,
 }
 
+// The first member of each cluster is used to represent the cluster when it is collapsed; semantic clusters and duplicate groups are tracked separately, so a node can belong to one of each
+#[derive(Debug)]
+pub struct Cluster {
+    pub kind: ClusterKind,
+    pub members: Vec<Ulid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScope {
+    Weave,
+    Documents,
+}
+
+const THREAD_PASSAGE_MAX_LENGTH: usize = 2048;
+const MAX_REQUEST_CAPTURES: usize = 1024;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NodeIndex {
     WithinNode(Ulid, usize),
@@ -104,13 +157,14 @@ impl SharedState {
         identifier: Ulid,
         runtime: Arc<Runtime>,
         client: Rc<RefCell<Option<InferenceClient>>>,
+        cache: InferenceCache,
         settings: &Settings,
     ) -> Self {
         Self {
             identifier,
             runtime,
             client,
-            cache: InferenceCache::default(),
+            cache,
             inference: settings.inference.default_parameters.clone(),
             cursor_node: NodeIndex::None,
             last_cursor_node: NodeIndex::None,
@@ -126,8 +180,28 @@ impl SharedState {
             has_opened_changed: false,
             requests: HashMap::with_capacity(128),
             responses: Vec::with_capacity(128),
+            new_captures: Vec::new(),
+            captures: LinkedHashMap::new(),
+            inspected_capture: None,
             seriation_requests: HashMap::with_capacity(32),
             seriation_responses: Vec::with_capacity(32),
+            scoring_requests: HashMap::with_capacity(8),
+            scoring_responses: Vec::with_capacity(8),
+            clustering_requests: HashMap::with_capacity(8),
+            clustering_responses: Vec::with_capacity(8),
+            clusters: HashMap::with_capacity(128),
+            collapsed_clusters: HashSet::with_capacity(32),
+            search_request: None,
+            search_index: SearchIndex::default(),
+            search_results: Vec::new(),
+            search_query: String::new(),
+            search_scope: SearchScope::Weave,
+            has_search_changed: false,
+            has_clusters_changed: false,
+            next_clusters_updated: false,
+            focus_search: false,
+            path: None,
+            open_requests: Vec::new(),
             last_ui_settings: settings.interface,
             has_theme_changed: false,
             last_activated_hovered: false,
@@ -165,11 +239,44 @@ impl SharedState {
             &self.cache,
             &mut self.requests,
             &mut self.responses,
+            &mut self.new_captures,
         );
+
+        for (id, capture) in self.new_captures.drain(..) {
+            self.captures.insert(id, capture);
+
+            if self.captures.len() > MAX_REQUEST_CAPTURES {
+                self.captures.pop_front();
+            }
+        }
         InferenceSettings::get_seriation_responses(
             &mut self.seriation_requests,
             &mut self.seriation_responses,
         );
+        InferenceParameters::get_scoring_responses(
+            &mut self.scoring_requests,
+            &mut self.scoring_responses,
+        );
+        InferenceSettings::get_clustering_responses(
+            &mut self.clustering_requests,
+            &mut self.clustering_responses,
+        );
+
+        self.has_search_changed = false;
+
+        if let Some(response) = InferenceSettings::get_search_response(&mut self.search_request) {
+            match response {
+                Ok(results) => {
+                    self.search_results = results;
+                }
+                Err(error) => {
+                    toasts.error(format!("Search failed: {error}"));
+                    warn!("Search failed: {error:#?}");
+                }
+            }
+
+            self.has_search_changed = true;
+        }
 
         if shortcuts.contains(Shortcuts::GenerateAtCursor) {
             match self.last_cursor_node {
@@ -582,7 +689,20 @@ impl SharedState {
                 }
                 Err(error) => {
                     toasts.error(format!("Inference failed: {error}"));
-                    warn!("Inference failed: {error:#?}");
+                    warn!("Inference failed: {error:#}");
+
+                    // Failed requests don't produce a node, so their capture is shown immediately
+                    if let Some(failed) = error.downcast_ref::<CapturedError>() {
+                        let identifier = Ulid::new();
+
+                        self.captures.insert(identifier, failed.capture.clone());
+
+                        if self.captures.len() > MAX_REQUEST_CAPTURES {
+                            self.captures.pop_front();
+                        }
+
+                        self.inspected_capture = Some(identifier);
+                    }
                 }
             }
         }
@@ -608,7 +728,62 @@ impl SharedState {
                 }
                 Err(error) => {
                     toasts.error(format!("Inference failed: {error}"));
-                    warn!("Inference failed: {error:#?}");
+                    warn!("Inference failed: {error:#}");
+                }
+            }
+        }
+        for response in self.scoring_responses.drain(..) {
+            match response {
+                Ok(nodes) => {
+                    for scored in nodes {
+                        let scored_bytes = scored.content.as_bytes().into_owned();
+
+                        if let Some(node) = weave.get_node(&scored.id)
+                            && let InnerNodeContent::Snippet(snippet) = &node.contents.content
+                            && *snippet == scored_bytes
+                        {
+                            let mut contents = node.contents.clone();
+                            contents.content = scored.content;
+
+                            if !settings.documents.store_counterfactual
+                                && let InnerNodeContent::Tokens(tokens) = &mut contents.content
+                            {
+                                for token in tokens {
+                                    token.1.shift_remove("counterfactual");
+                                }
+                            }
+
+                            if let Some(perplexity) = scored.perplexity {
+                                contents.metadata.insert(
+                                    "perplexity".to_string(),
+                                    ((perplexity * 100.0).round() / 100.0).to_string(),
+                                );
+                            }
+
+                            weave.set_node_contents(&scored.id, contents);
+                        }
+                    }
+                }
+                Err(error) => {
+                    toasts.error(format!("Scoring failed: {error}"));
+                    warn!("Scoring failed: {error:#?}");
+                }
+            }
+        }
+        for response in self.clustering_responses.drain(..) {
+            match response {
+                Ok(response) => {
+                    for member in response.clusters.iter().flatten() {
+                        self.remove_cluster(response.kind, member);
+                    }
+
+                    for cluster in response.clusters {
+                        self.insert_cluster(response.kind, cluster, true);
+                    }
+                }
+                Err(error) => {
+                    toasts.error(format!("Clustering failed: {error}"));
+                    warn!("Clustering failed: {error:#?}");
                 }
             }
         }
@@ -616,12 +791,21 @@ impl SharedState {
         self.has_weave_layout_changed = weave.has_layout_changed();
         self.has_weave_changed = weave.has_changed();
 
+        if self.has_weave_changed && !self.clusters.is_empty() {
+            self.prune_clusters(weave);
+        }
+
+        self.has_clusters_changed = self.next_clusters_updated;
+        self.next_clusters_updated = false;
+
         if self.has_weave_changed
             || self.has_weave_layout_changed
             || self.has_cursor_node_changed
             || self.has_hover_node_changed
             || self.has_theme_changed
             || self.has_opened_changed
+            || self.has_search_changed
+            || self.has_clusters_changed
         {
             ctx.request_repaint();
         }
@@ -780,17 +964,7 @@ impl SharedState {
             return;
         }
 
-        let content: Vec<TokensOrBytes> = if let Some(parent) = parent {
-            let thread: Vec<u128> = weave.get_thread_from_u128(&parent.0).rev().collect();
-
-            thread
-                .into_iter()
-                .filter_map(|id| weave.get_node_u128(&id))
-                .map(|node| node.contents.content.clone().into())
-                .collect()
-        } else {
-            vec![]
-        };
+        let content = thread_content(weave, parent);
 
         if let Some(client) = self.client.borrow().as_ref() {
             self.inference.create_request(
@@ -807,14 +981,560 @@ impl SharedState {
                 .push(Err(anyhow::Error::msg("Client is not initialized")));
         }
     }
+    pub fn render_inference_parameters(&mut self, settings: &Settings, ui: &mut Ui) {
+        self.inference.render(&settings.inference, &self.cache, ui);
+    }
+    pub fn regenerate_like(&mut self, weave: &mut WeaveWrapper, node: Ulid, settings: &Settings) {
+        if let Some((parent, record)) = weave.get_node(&node).and_then(|node| {
+            GenerationRecord::from_metadata(&node.contents.metadata)
+                .map(|record| (node.from.map(Ulid), record))
+        }) {
+            let parameters = self.inference.with_record(&record);
+            let content = thread_content(weave, parent);
+
+            if let Some(client) = self.client.borrow().as_ref() {
+                parameters.create_request(
+                    &settings.inference,
+                    &self.runtime,
+                    client,
+                    &self.cache,
+                    parent,
+                    content,
+                    &mut self.requests,
+                );
+            } else {
+                self.responses
+                    .push(Err(anyhow::Error::msg("Client is not initialized")));
+            }
+        }
+    }
+    pub fn copy_generation_parameters(&mut self, node: &TapestryNode) {
+        if let Some(record) = GenerationRecord::from_metadata(&node.contents.metadata) {
+            self.inference.apply_record(&record);
+        }
+    }
+    pub fn score_thread(&mut self, weave: &mut WeaveWrapper, node: Ulid, settings: &Settings) {
+        let thread: Vec<u128> = weave.get_thread_from_u128(&node.0).rev().collect();
+
+        let thread: Vec<(Ulid, InnerNodeContent)> = thread
+            .into_iter()
+            .filter_map(|id| weave.get_node_u128(&id))
+            .map(|node| (Ulid(node.id), node.contents.content.clone()))
+            .collect();
+
+        if !thread.iter().any(|(_, content)| match content {
+            InnerNodeContent::Snippet(snippet) => !snippet.is_empty(),
+            InnerNodeContent::Tokens(_) => false,
+        }) {
+            self.scoring_responses
+                .push(Err(anyhow::Error::msg("Thread has no unscored text")));
+            return;
+        }
+
+        if let Some(client) = self.client.borrow().as_ref() {
+            self.inference.create_scoring_request(
+                &settings.inference,
+                &self.runtime,
+                client,
+                &self.cache,
+                thread,
+                &mut self.scoring_requests,
+            );
+        } else {
+            self.scoring_responses
+                .push(Err(anyhow::Error::msg("Client is not initialized")));
+        }
+    }
+    pub fn cluster_children(
+        &mut self,
+        weave: &mut WeaveWrapper,
+        parent: Option<Ulid>,
+        settings: &Settings,
+    ) {
+        if let Some(request) = sibling_contents(weave, parent) {
+            if let Some(client) = self.client.borrow().as_ref() {
+                settings.inference.create_clustering_request(
+                    &self.runtime,
+                    client,
+                    &self.cache,
+                    (parent, request),
+                    &mut self.clustering_requests,
+                );
+            } else {
+                self.clustering_responses
+                    .push(Err(anyhow::Error::msg("Client is not initialized")));
+            }
+        }
+    }
+    pub fn clear_clusters(&mut self, weave: &WeaveWrapper, parent: Option<Ulid>) {
+        let children: Vec<u128> = if let Some(parent) = parent {
+            if let Some(parent) = weave.get_node(&parent) {
+                parent.to.iter().copied().collect()
+            } else {
+                return;
+            }
+        } else {
+            weave.get_roots_u128_direct().iter().copied().collect()
+        };
+
+        for child in children {
+            for kind in ClusterKind::ALL {
+                self.remove_cluster(kind, &Ulid(child));
+            }
+        }
+    }
+    pub fn has_clusters(&self, weave: &WeaveWrapper, parent: Option<Ulid>) -> bool {
+        if let Some(parent) = parent {
+            weave.get_node(&parent).is_some_and(|parent| {
+                parent
+                    .to
+                    .iter()
+                    .any(|child| self.is_clustered(&Ulid(*child)))
+            })
+        } else {
+            weave
+                .get_roots_u128_direct()
+                .iter()
+                .any(|child| self.is_clustered(&Ulid(*child)))
+        }
+    }
+    fn is_clustered(&self, id: &Ulid) -> bool {
+        ClusterKind::ALL
+            .iter()
+            .any(|kind| self.clusters.contains_key(&(*kind, *id)))
+    }
+    pub fn get_cluster(&self, kind: ClusterKind, id: &Ulid) -> Option<&Cluster> {
+        self.clusters
+            .get(&(kind, *id))
+            .map(|cluster| cluster.as_ref())
+    }
+    pub fn is_cluster_collapsed(&self, kind: ClusterKind, id: &Ulid) -> bool {
+        self.collapsed_clusters.contains(&(kind, *id))
+    }
+    pub fn toggle_cluster(&mut self, kind: ClusterKind, id: Ulid) {
+        if !self.collapsed_clusters.remove(&(kind, id)) {
+            self.collapsed_clusters.insert((kind, id));
+        }
+
+        self.next_clusters_updated = true;
+    }
+    // The number of distinct nodes hidden behind the specified node by its collapsed clusters
+    pub fn collapsed_cluster_size(&self, id: &Ulid) -> usize {
+        let mut hidden = HashSet::new();
+
+        for kind in ClusterKind::ALL {
+            if let Some(cluster) = self.clusters.get(&(kind, *id))
+                && cluster.members[0] == *id
+                && self.collapsed_clusters.contains(&(kind, *id))
+            {
+                hidden.extend(cluster.members[1..].iter().copied());
+            }
+        }
+
+        hidden.len()
+    }
+    // Active nodes are never hidden, so that the current thread always remains visible
+    pub fn is_hidden_by_cluster(&self, weave: &WeaveWrapper, id: &Ulid) -> bool {
+        let hidden = ClusterKind::ALL.iter().any(|kind| {
+            self.clusters.get(&(*kind, *id)).is_some_and(|cluster| {
+                cluster.members[0] != *id
+                    && self
+                        .collapsed_clusters
+                        .contains(&(*kind, cluster.members[0]))
+            })
+        });
+
+        hidden && !weave.get_node(id).is_some_and(|node| node.active)
+    }
+    // Cluster members are displayed next to the first visible member, without reordering the weave itself; duplicate groups are kept together within semantic clusters
+    pub fn group_by_cluster(&self, items: &mut [Ulid]) {
+        if self.clusters.is_empty() {
+            return;
+        }
+
+        let positions: HashMap<Ulid, usize> = items
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (*id, index))
+            .collect();
+
+        let anchor =
+            |kind: ClusterKind, id: &Ulid, position: usize| match self.clusters.get(&(kind, *id)) {
+                Some(cluster) => cluster
+                    .members
+                    .iter()
+                    .filter_map(|member| positions.get(member))
+                    .min()
+                    .copied()
+                    .unwrap_or(position),
+                None => position,
+            };
+
+        items.sort_by_cached_key(|id| {
+            let position = positions.get(id).copied().unwrap_or(usize::MAX);
+
+            (
+                anchor(ClusterKind::Semantic, id, position),
+                anchor(ClusterKind::Duplicate, id, position),
+                position,
+            )
+        });
+    }
+    fn insert_cluster(&mut self, kind: ClusterKind, members: Vec<Ulid>, collapsed: bool) {
+        if members.len() > 1 {
+            if collapsed {
+                self.collapsed_clusters.insert((kind, members[0]));
+            }
+
+            let cluster = Arc::new(Cluster { kind, members });
+
+            for member in &cluster.members {
+                self.clusters.insert((kind, *member), cluster.clone());
+            }
+
+            self.next_clusters_updated = true;
+        }
+    }
+    fn remove_cluster(&mut self, kind: ClusterKind, member: &Ulid) {
+        if let Some(cluster) = self.clusters.remove(&(kind, *member)) {
+            self.collapsed_clusters.remove(&(kind, cluster.members[0]));
+
+            for member in &cluster.members {
+                self.clusters.remove(&(kind, *member));
+            }
+
+            self.next_clusters_updated = true;
+        }
+    }
+    fn prune_clusters(&mut self, weave: &WeaveWrapper) {
+        let mut visited = HashSet::with_capacity(self.clusters.len());
+
+        let stale: Vec<Arc<Cluster>> = self
+            .clusters
+            .values()
+            .filter(|cluster| {
+                visited.insert((cluster.kind, cluster.members[0]))
+                    && cluster
+                        .members
+                        .iter()
+                        .any(|member| weave.get_node(member).is_none())
+            })
+            .cloned()
+            .collect();
+
+        for cluster in stale {
+            let collapsed = self
+                .collapsed_clusters
+                .contains(&(cluster.kind, cluster.members[0]));
+            self.remove_cluster(cluster.kind, &cluster.members[0]);
+
+            let members: Vec<Ulid> = cluster
+                .members
+                .iter()
+                .copied()
+                .filter(|member| weave.get_node(member).is_some())
+                .collect();
+
+            self.insert_cluster(cluster.kind, members, collapsed);
+        }
+    }
+    pub fn find_duplicates(
+        &mut self,
+        weave: &mut WeaveWrapper,
+        parent: Option<Ulid>,
+        settings: &Settings,
+    ) {
+        if let Some(request) = sibling_contents(weave, parent) {
+            settings.inference.create_duplicate_request(
+                &self.runtime,
+                self.client.borrow().as_ref(),
+                &self.cache,
+                (parent, request),
+                &mut self.clustering_requests,
+            );
+        }
+    }
+    // Duplicates with children or on the active thread are never deleted, as removing them would also remove their subtree or the current thread
+    pub fn deletable_duplicates(&self, weave: &WeaveWrapper, keep: &Ulid) -> Vec<Ulid> {
+        if let Some(cluster) = self.clusters.get(&(ClusterKind::Duplicate, *keep)) {
+            cluster
+                .members
+                .iter()
+                .filter(|member| {
+                    *member != keep
+                        && weave
+                            .get_node(member)
+                            .is_some_and(|node| node.to.is_empty() && !node.active)
+                })
+                .copied()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+    // Keeps the specified node, removing the deletable members of its duplicate group from the weave; the group itself is updated once the weave changes
+    pub fn delete_cluster_duplicates(&mut self, weave: &mut WeaveWrapper, keep: Ulid) {
+        for member in self.deletable_duplicates(weave, &keep) {
+            weave.remove_node(&member);
+        }
+    }
+    pub fn search(&mut self, weave: &mut WeaveWrapper, settings: &Settings, exclude: Option<Ulid>) {
+        if self.search_query.trim().is_empty() {
+            return;
+        }
+
+        let mut passages = collect_passages(weave, None, exclude);
+
+        let documents = match self.search_scope {
+            SearchScope::Weave => None,
+            SearchScope::Documents => {
+                Some((settings.documents.location.clone(), self.path.clone()))
+            }
+        };
+
+        let loader = Box::new(move || {
+            if let Some((location, current)) = documents {
+                for entry in WalkDir::new(location)
+                    .into_iter()
+                    .filter_map(|entry| entry.ok())
+                {
+                    let path = entry.path();
+
+                    if entry.file_type().is_file()
+                        && path.extension() == Some(OsStr::new(VERSIONED_WEAVE_FILE_EXTENSION))
+                        && current.as_deref() != Some(path)
+                    {
+                        passages.append(&mut load_passages(path));
+                    }
+                }
+            }
+
+            passages
+        });
+
+        settings.inference.create_search_request(
+            &self.runtime,
+            self.client.borrow().as_ref(),
+            &self.cache,
+            &self.search_index,
+            self.search_query.as_bytes().to_vec(),
+            loader,
+            &mut self.search_request,
+        );
+    }
+    pub fn find_similar(&mut self, weave: &mut WeaveWrapper, node: Ulid, settings: &Settings) {
+        if let Some(content) = weave
+            .get_node(&node)
+            .map(|node| String::from_utf8_lossy(&node.contents.content.as_bytes()).into_owned())
+        {
+            self.search_query = content.trim().to_string();
+            self.focus_search = true;
+            self.search(weave, settings, Some(node));
+        }
+    }
+    pub fn get_search_results(&self) -> &[SearchResult] {
+        &self.search_results
+    }
+    pub fn is_searching(&self) -> bool {
+        self.search_request.is_some()
+    }
+    pub fn take_search_focus(&mut self) -> bool {
+        std::mem::take(&mut self.focus_search)
+    }
+    pub fn set_path(&mut self, path: Option<PathBuf>) {
+        self.path = path;
+    }
+    pub fn open_weave(&mut self, path: PathBuf) {
+        self.open_requests.push(path);
+    }
+    pub fn take_open_requests(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.open_requests)
+    }
+    pub fn get_capture(&self, id: &Ulid) -> Option<&Arc<RequestCapture>> {
+        self.captures.get(id)
+    }
+    pub fn inspect_capture(&mut self, id: Ulid) {
+        self.inspected_capture = Some(id);
+    }
+    pub fn get_inspected_capture(&self) -> Option<(Ulid, Arc<RequestCapture>)> {
+        self.inspected_capture
+            .and_then(|id| self.captures.get(&id).map(|capture| (id, capture.clone())))
+    }
+    pub fn close_capture_inspector(&mut self) {
+        self.inspected_capture = None;
+    }
+    pub fn replay_capture(&mut self, weave: &WeaveWrapper, node: Ulid, settings: &Settings) {
+        if let Some(capture) = self.captures.get(&node).cloned()
+            && let Some(node) = weave.get_node(&node)
+        {
+            if let Some(client) = self.client.borrow().as_ref() {
+                settings.inference.create_replay_request(
+                    &self.runtime,
+                    client,
+                    node.from.map(Ulid),
+                    node.contents.model.clone(),
+                    capture,
+                    &mut self.requests,
+                );
+            } else {
+                self.responses
+                    .push(Err(anyhow::Error::msg("Client is not initialized")));
+            }
+        }
+    }
     pub fn get_request_count(&self) -> usize {
-        self.requests.len() + self.seriation_requests.len()
+        self.requests.len()
+            + self.seriation_requests.len()
+            + self.scoring_requests.len()
+            + self.clustering_requests.len()
+            + usize::from(self.search_request.is_some())
     }
     pub fn cancel_requests(&mut self) {
         self.requests.clear();
         self.responses.clear();
         self.seriation_requests.clear();
         self.seriation_responses.clear();
+        self.scoring_requests.clear();
+        self.scoring_responses.clear();
+        self.clustering_requests.clear();
+        self.clustering_responses.clear();
+        self.search_request = None;
+    }
+}
+
+fn thread_content(
+    weave: &mut WeaveWrapper,
+    parent: Option<Ulid>,
+) -> Vec<(TokensOrBytes, SegmentSource)> {
+    if let Some(parent) = parent {
+        let thread: Vec<u128> = weave.get_thread_from_u128(&parent.0).rev().collect();
+
+        thread
+            .into_iter()
+            .filter_map(|id| weave.get_node_u128(&id))
+            .map(|node| {
+                (
+                    node.contents.content.clone().into(),
+                    if node.contents.model.is_some() {
+                        SegmentSource::Model
+                    } else {
+                        SegmentSource::Human
+                    },
+                )
+            })
+            .collect()
+    } else {
+        vec![]
+    }
+}
+
+fn sibling_contents(weave: &WeaveWrapper, parent: Option<Ulid>) -> Option<Vec<(Ulid, Vec<u8>)>> {
+    let request: Vec<(Ulid, Vec<u8>)> = if let Some(parent) = parent {
+        &weave.get_node(&parent)?.to
+    } else {
+        weave.get_roots_u128_direct()
+    }
+    .iter()
+    .filter_map(|id| weave.get_node_u128(id))
+    .filter(|child| match &child.contents.content {
+        InnerNodeContent::Tokens(tokens) => tokens.len() != 1,
+        InnerNodeContent::Snippet(_) => true,
+    })
+    .map(|child| (Ulid(child.id), child.contents.content.as_bytes().to_vec()))
+    .collect();
+
+    if request.len() > 1 {
+        Some(request)
+    } else {
+        None
+    }
+}
+
+fn collect_passages(
+    weave: &mut WeaveWrapper,
+    path: Option<Arc<PathBuf>>,
+    exclude: Option<Ulid>,
+) -> Vec<Passage> {
+    let identifiers = weave.dump_identifiers_ordered_u128();
+    let mut passages = Vec::with_capacity(identifiers.len());
+    let mut leaves = Vec::new();
+
+    for id in identifiers {
+        if exclude == Some(Ulid(id)) {
+            continue;
+        }
+
+        if let Some(node) = weave.get_node_u128(&id) {
+            if let InnerNodeContent::Tokens(tokens) = &node.contents.content
+                && tokens.len() == 1
+            {
+                continue;
+            }
+
+            if node.to.is_empty() && node.from.is_some() {
+                leaves.push(id);
+            }
+
+            let content = node.contents.content.as_bytes();
+
+            if content.iter().any(|byte| !byte.is_ascii_whitespace()) {
+                passages.push(Passage {
+                    path: path.clone(),
+                    node: Ulid(id),
+                    kind: PassageKind::Node,
+                    content: content.to_vec(),
+                });
+            }
+        }
+    }
+
+    for leaf in leaves {
+        let thread: Vec<u128> = weave.get_thread_from_u128(&leaf).rev().collect();
+
+        let content: Vec<u8> = thread
+            .into_iter()
+            .filter_map(|id| weave.get_node_u128(&id))
+            .flat_map(|node| node.contents.content.as_bytes().to_vec())
+            .collect();
+
+        // Only the end of each thread is embedded, as embedding models have limited context lengths
+        let mut start = content.len().saturating_sub(THREAD_PASSAGE_MAX_LENGTH);
+
+        while start < content.len() && (content[start] & 0b1100_0000) == 0b1000_0000 {
+            start += 1;
+        }
+
+        passages.push(Passage {
+            path: path.clone(),
+            node: Ulid(leaf),
+            kind: PassageKind::Thread,
+            content: content[start..].to_vec(),
+        });
+    }
+
+    passages
+}
+
+fn load_passages(path: &Path) -> Vec<Passage> {
+    match fs::read(path) {
+        Ok(bytes) => match VersionedWeave::from_bytes(&bytes) {
+            Some(Ok(weave)) => {
+                let mut weave: WeaveWrapper = weave.into_latest().into();
+                collect_passages(&mut weave, Some(Arc::new(path.to_path_buf())), None)
+            }
+            Some(Err(error)) => {
+                warn!("Unable to search {}: {error:#?}", path.display());
+                vec![]
+            }
+            None => {
+                warn!("Unable to search {}: Invalid weave header", path.display());
+                vec![]
+            }
+        },
+        Err(error) => {
+            warn!("Unable to search {}: {error:#?}", path.display());
+            vec![]
+        }
     }
 }
 
@@ -886,7 +1606,10 @@ pub fn render_node_metadata_tooltip(ui: &mut Ui, node: &TapestryNode) {
                     ui.label(format!("confidence: {:.2} (k = {k})", confidence));
                 }
             }
-        } else if !(key == "confidence_k" || key == "confidence_n") {
+        } else if !(key == "confidence_k"
+            || key == "confidence_n"
+            || key == GenerationRecord::METADATA_KEY)
+        {
             ui.label(format!("{key}: {value}"));
         }
     }
@@ -897,6 +1620,50 @@ pub fn render_node_metadata_tooltip(ui: &mut Ui, node: &TapestryNode) {
     ui.label(Ulid(node.id).to_string());
 }
 
+pub fn render_request_capture(ui: &mut Ui, capture: &RequestCapture) {
+    ui.label(format!("POST {}", capture.endpoint));
+    ui.label(format!(
+        "Sent {}, response received after {:.2}s",
+        format_time(capture.started),
+        capture.duration.as_secs_f32()
+    ));
+
+    CollapsingHeader::new("Request headers")
+        .id_salt("request_headers")
+        .show(ui, |ui| {
+            for (key, value) in capture.redacted_headers() {
+                ui.label(format!("{key}: {value}"));
+            }
+        });
+
+    for (label, value) in [
+        ("Request body", &capture.request),
+        ("Response body", &capture.response),
+    ] {
+        CollapsingHeader::new(label)
+            .id_salt(label)
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut text = serde_json::to_string_pretty(value).unwrap_or_default();
+
+                if ui.button("\u{E09E}").on_hover_text("Copy JSON").clicked() {
+                    ui.ctx().copy_text(text.clone());
+                }
+
+                ScrollArea::vertical()
+                    .id_salt(label)
+                    .max_height(ui.available_height() / 2.0)
+                    .show(ui, |ui| {
+                        TextEdit::multiline(&mut text)
+                            .code_editor()
+                            .interactive(false)
+                            .desired_width(f32::INFINITY)
+                            .ui(ui);
+                    });
+            });
+    }
+}
+
 pub fn render_token_tooltip(ui: &mut Ui, token: &[u8], token_metadata: &MetadataMap) {
     if token_metadata
         .get("original_length")
@@ -1046,6 +1813,86 @@ pub fn get_token_color(
     }
 }
 
+// Returns the node's confidence, falling back to the confidence of the node's token for single-token nodes
+pub fn calculate_confidence(node: &TapestryNode) -> Option<f32> {
+    node.contents
+        .metadata
+        .get("confidence")
+        .or_else(|| match &node.contents.content {
+            InnerNodeContent::Tokens(tokens) if tokens.len() == 1 => tokens[0].1.get("confidence"),
+            _ => None,
+        })
+        .and_then(|value| value.parse::<f32>().ok())
+}
+
+pub fn calculate_token_logprob(token_metadata: &MetadataMap) -> Option<f32> {
+    token_metadata
+        .get("probability")
+        .and_then(|value| value.parse::<f32>().ok())
+        .map(|probability| probability.clamp(f32::EPSILON, 1.0).ln())
+}
+
+// Estimates the entropy (in nats) of the distribution a token was sampled from, using the top tokens stored alongside it
+pub fn calculate_token_entropy(token_metadata: &MetadataMap) -> Option<f32> {
+    let counterfactual =
+        deserialize_counterfactual_logprobs(token_metadata.get("counterfactual")?)?;
+
+    let probabilities: Vec<f32> = counterfactual
+        .iter()
+        .filter_map(|(_, metadata)| {
+            metadata
+                .get("probability")
+                .and_then(|value| value.parse::<f32>().ok())
+        })
+        .filter(|probability| *probability > 0.0)
+        .collect();
+
+    if probabilities.is_empty() {
+        None
+    } else {
+        Some(
+            probabilities
+                .into_iter()
+                .map(|probability| -probability * probability.ln())
+                .sum(),
+        )
+    }
+}
+
+pub fn calculate_average_logprob(node: &TapestryNode) -> Option<f32> {
+    if let InnerNodeContent::Tokens(tokens) = &node.contents.content {
+        average(
+            tokens
+                .iter()
+                .filter_map(|(_, token_metadata)| calculate_token_logprob(token_metadata)),
+        )
+    } else {
+        None
+    }
+}
+
+pub fn calculate_average_entropy(node: &TapestryNode) -> Option<f32> {
+    if let InnerNodeContent::Tokens(tokens) = &node.contents.content {
+        average(
+            tokens
+                .iter()
+                .filter_map(|(_, token_metadata)| calculate_token_entropy(token_metadata)),
+        )
+    } else {
+        None
+    }
+}
+
+fn average(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+
+    if count > 0 {
+        Some(sum / count as f32)
+    } else {
+        None
+    }
+}
+
 pub fn get_node_color(node: &TapestryNode, settings: &Settings) -> Option<Color32> {
     if settings.interface.show_model_colors {
         if settings.interface.override_model_colors
@@ -13,7 +13,7 @@ use tapestry_weave::{
         indexmap::{IndexMap, IndexSet},
         rkyv::rancor,
     },
-    v0::{InnerNodeContent, MetadataMap, TapestryNode, TapestryWeave},
+    v0::{InnerNodeContent, MetadataMap, NodeContent, TapestryNode, TapestryWeave},
 };
 
 pub struct WeaveWrapper {
@@ -218,14 +218,12 @@ impl WeaveWrapper {
         self.layout_changed = true;
         self.weave.weave.merge_with_parent(id)
     }*/
-    pub fn split_node(&mut self, id: &Ulid, at: usize) -> bool {
+    pub fn split_node(&mut self, id: &Ulid, at: usize) -> Option<Ulid> {
         self.changed = true;
         self.layout_changed = true;
-        self.weave
-            .split_node(id, at, |timestamp| {
-                Ulid::from_datetime(SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp))
-            })
-            .is_some()
+        self.weave.split_node(id, at, |timestamp| {
+            Ulid::from_datetime(SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp))
+        })
     }
     pub fn split_out_token(
         &mut self,
@@ -315,6 +313,17 @@ impl WeaveWrapper {
         self.layout_changed = true;
         self.weave.weave.remove_node(id).is_some()
     }
+    pub fn set_node_contents(&mut self, id: &Ulid, contents: NodeContent) -> bool {
+        if let Some(node_contents) = self.weave.weave.get_contents_mut(&id.0) {
+            *node_contents = contents;
+            self.changed = true;
+            self.layout_changed = true;
+
+            true
+        } else {
+            false
+        }
+    }
     pub fn set_active_content(&mut self, value: &[u8], metadata: MetadataMap) -> bool {
         self.changed = true;
         self.layout_changed = true;
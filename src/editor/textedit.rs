@@ -2,8 +2,8 @@ use std::{cell::RefCell, collections::HashMap, ops::Range, rc::Rc};
 
 use eframe::{
     egui::{
-        Color32, Frame, Galley, Id, Mesh, Pos2, Rect, ScrollArea, Sense, TextBuffer, TextEdit,
-        TextFormat, TextStyle, Tooltip, Ui,
+        Align, Color32, Frame, Galley, Id, Mesh, Pos2, Rect, ScrollArea, Sense, TextBuffer,
+        TextEdit, TextFormat, TextStyle, Tooltip, Ui,
         text::{CCursor, CCursorRange, LayoutJob, LayoutSection, TextWrapping},
     },
     epaint::{MarginF32, Vertex, WHITE_UV},
@@ -17,9 +17,12 @@ use tapestry_weave::{
 };
 
 use crate::{
-    editor::shared::{
-        NodeIndex, SharedState, get_node_color, get_token_color, render_node_metadata_tooltip,
-        render_token_counterfactual_tooltip, render_token_tooltip, weave::WeaveWrapper,
+    editor::{
+        lists::render_node_context_menu,
+        shared::{
+            NodeIndex, SharedState, get_node_color, get_token_color, render_node_metadata_tooltip,
+            render_token_counterfactual_tooltip, render_token_tooltip, weave::WeaveWrapper,
+        },
     },
     settings::{Settings, shortcuts::Shortcuts},
 };
@@ -37,11 +40,9 @@ pub struct TextEditorView {
     last_text_edit_rect: Rect,
     text_edit_last_changed: bool,
     should_update_rects: bool,
+    context_menu_target: Option<(Ulid, usize)>,
 }
 
-// TODO: Implement a context menu on the TextEdit
-// Currently stuck on lacking APIs in egui; see https://github.com/emilk/egui/issues/4393
-
 // TODO: Implement Ctrl+F in TextEdit
 
 type Snippet = (usize, Ulid, Color32, Option<usize>);
@@ -72,6 +73,7 @@ impl Default for TextEditorView {
             },
             text_edit_last_changed: false,
             should_update_rects: false,
+            context_menu_target: None,
         }
     }
 }
@@ -105,7 +107,7 @@ impl TextEditorView {
         &mut self,
         ui: &mut Ui,
         weave: &mut WeaveWrapper,
-        settings: &Settings,
+        settings: &mut Settings,
         _toasts: &mut Toasts,
         state: &mut SharedState,
         _shortcuts: FlagSet<Shortcuts>,
@@ -172,19 +174,45 @@ impl TextEditorView {
                         if self.last_seen_cursor_node != state.get_cursor_node() {
                             // TODO: Rewrite this to properly change the cursor position
                             if !(textedit.response.changed() || self.text_edit_last_changed) {
-                                let index = self.text.chars().count();
+                                // Positions within a node (such as tokens selected in other views) are moved to directly, while other changes move the cursor to the end of the text
+                                let within_node_index = match state.get_cursor_node() {
+                                    NodeIndex::WithinNode(node, position) => self
+                                        .node_snippets
+                                        .get(&node)
+                                        .and_then(|ranges| ranges.first())
+                                        .map(|range| {
+                                            let byte_index = self.text.floor_char_boundary(
+                                                (range.start + position).min(self.text.len()),
+                                            );
+
+                                            self.text[..byte_index].chars().count()
+                                        }),
+                                    _ => None,
+                                };
+                                let index =
+                                    within_node_index.unwrap_or_else(|| self.text.chars().count());
+                                let cursor = CCursor {
+                                    index,
+                                    prefer_next_row: true,
+                                };
+
                                 textedit.state.cursor.set_char_range(Some(CCursorRange {
-                                    primary: CCursor {
-                                        index,
-                                        prefer_next_row: true,
-                                    },
-                                    secondary: CCursor {
-                                        index,
-                                        prefer_next_row: true,
-                                    },
+                                    primary: cursor,
+                                    secondary: cursor,
                                     h_pos: None,
                                 }));
                                 textedit.state.store(ui.ctx(), textedit.response.id);
+
+                                if within_node_index.is_some() {
+                                    ui.scroll_to_rect(
+                                        textedit
+                                            .galley
+                                            .pos_from_cursor(cursor)
+                                            .translate(textedit.galley_pos.to_vec2()),
+                                        Some(Align::Center),
+                                    );
+                                    textedit.response.request_focus();
+                                }
                             }
                             self.last_seen_cursor_node = state.get_cursor_node();
                         }
@@ -227,6 +255,7 @@ impl TextEditorView {
                         let mut last_node = Ulid(0);
                         let mut byte_index: usize = 0;
                         let mut token_index: usize = 0;
+                        let mut pointer_snippet = None;
 
                         absolute_snippet_row_positions(
                             &self.snippets.borrow(),
@@ -245,6 +274,8 @@ impl TextEditorView {
                                 }
 
                                 if response.contains_pointer() {
+                                    pointer_snippet = Some((snippet.1, snippet.3));
+
                                     if let Some(within_index) = snippet.3 {
                                         state.set_hovered_node(NodeIndex::WithinNode(
                                             snippet.1,
@@ -310,6 +341,39 @@ impl TextEditorView {
                             },
                         );
 
+                        // Tokens are targeted as a whole, while positions within snippets are found using the galley
+                        if textedit.response.secondary_clicked() {
+                            self.context_menu_target =
+                                match pointer_snippet {
+                                    Some((node, Some(position))) => Some((node, position)),
+                                    _ => textedit.response.interact_pointer_pos().and_then(
+                                        |pointer| {
+                                            let cursor = textedit
+                                                .galley
+                                                .cursor_from_pos(pointer - textedit.galley_pos);
+
+                                            self.calculate_cursor(weave, Some(cursor.index))
+                                                .and_then(|(node, index)| {
+                                                    calculate_cursor_index(
+                                                        node,
+                                                        index,
+                                                        &self.node_snippets,
+                                                    )
+                                                    .map(|position| (node, position))
+                                                })
+                                        },
+                                    ),
+                                };
+                        }
+
+                        textedit.response.context_menu(|ui| {
+                            if let Some((node, position)) = self.context_menu_target {
+                                self.render_context_menu(
+                                    ui, weave, settings, state, node, position,
+                                );
+                            }
+                        });
+
                         if textedit.response.changed() {
                             self.update_weave(state, weave);
                             self.last_text_edit_cursor = None;
@@ -351,6 +415,112 @@ impl TextEditorView {
                     });
             });
     }
+    fn render_context_menu(
+        &self,
+        ui: &mut Ui,
+        weave: &mut WeaveWrapper,
+        settings: &mut Settings,
+        state: &mut SharedState,
+        node: Ulid,
+        position: usize,
+    ) {
+        if let Some(node) = weave.get_node(&node).cloned() {
+            let id = Ulid(node.id);
+            let length = match &node.contents.content {
+                InnerNodeContent::Snippet(snippet) => snippet.len(),
+                InnerNodeContent::Tokens(tokens) => tokens.iter().map(|token| token.0.len()).sum(),
+            };
+            let position = position.min(length);
+            let token = match &node.contents.content {
+                InnerNodeContent::Tokens(tokens) => {
+                    let mut offset = 0;
+
+                    tokens
+                        .iter()
+                        .enumerate()
+                        .find_map(|(index, (token, token_metadata))| {
+                            offset += token.len();
+                            (offset > position).then_some((index, token_metadata))
+                        })
+                }
+                InnerNodeContent::Snippet(_) => None,
+            };
+
+            if ui
+                .button("Generate here")
+                .on_hover_text("Generates completions starting from this position, splitting the node if the position is within it.")
+                .clicked()
+            {
+                let parent = if position == 0 {
+                    node.from.map(Ulid)
+                } else {
+                    if position < length {
+                        weave.split_node(&id, position);
+                    }
+                    Some(id)
+                };
+
+                state.generate_children(weave, parent, settings);
+            }
+
+            if position > 0 && position < length && ui.button("Split here").clicked() {
+                weave.split_node(&id, position);
+            }
+
+            if let Some((index, _)) = token
+                && !matches!(&node.contents.content, InnerNodeContent::Tokens(tokens) if tokens.len() == 1)
+                && ui
+                    .button("Split out token")
+                    .on_hover_text("Moves this token into its own node, splitting the text before and after it into separate nodes.")
+                    .clicked()
+            {
+                weave.split_out_token(&id, index);
+            }
+
+            if let Some((index, token_metadata)) = token
+                && token_metadata.contains_key("counterfactual")
+            {
+                ui.menu_button("Show alternatives", |ui| {
+                    if let (_, Some(choice)) =
+                        render_token_counterfactual_tooltip(ui, token_metadata)
+                    {
+                        add_counterfactual_sibling(weave, id, index, choice);
+                    }
+                });
+            }
+
+            if position < length
+                && ui
+                    .button("Delete from here")
+                    .on_hover_text("Deletes the text from this position onwards, along with all of the node's children.")
+                    .clicked()
+            {
+                if position == 0 {
+                    weave.remove_node(&id);
+                } else if let Some(tail) = weave.split_node(&id, position) {
+                    weave.remove_node(&tail);
+                }
+            }
+
+            if let Some(range) = self
+                .node_snippets
+                .get(&id)
+                .and_then(|ranges| ranges.first())
+                && ui.button("Copy thread up to here").clicked()
+            {
+                let bytes = self.bytes.borrow();
+
+                ui.ctx().copy_text(
+                    String::from_utf8_lossy(&bytes[..(range.start + position).min(bytes.len())])
+                        .to_string(),
+                );
+            }
+
+            ui.separator();
+
+            render_node_context_menu(ui, settings, state, weave, &node, false);
+        }
+    }
     fn update_contents(
         &mut self,
         weave: &mut WeaveWrapper,
@@ -960,39 +1130,9 @@ fn render_tooltip(ui: &mut Ui, weave: &mut WeaveWrapper, node: Ulid, index: usiz
 
                     render_node_metadata_tooltip(ui, node);
 
-                    if let Some(counterfactual_index) = counterfactual_choice
-                        && let Some(value) = token_metadata.get("counterfactual").cloned()
-                        && let Some(counterfactual) = deserialize_counterfactual_logprobs(&value)
-                        && let Some(mut counterfactual_token) =
-                            counterfactual.get(counterfactual_index).cloned()
-                    {
-                        let metadata = node.contents.metadata.clone();
-                        let model = node.contents.model.clone();
-
+                    if let Some(counterfactual_index) = counterfactual_choice {
                         let node = Ulid(node.id);
-                        if let Some(split) = weave.split_out_token(&node, index) {
-                            let active = weave
-                                .get_active_thread_u128()
-                                .collect::<Vec<_>>()
-                                .contains(&node.0);
-
-                            counterfactual_token
-                                .1
-                                .insert("counterfactual".to_string(), value);
-
-                            weave.add_node(TapestryNode {
-                                id: Ulid::new().0,
-                                from: split.0.map(|id| id.0),
-                                to: IndexSet::default(),
-                                active,
-                                bookmarked: false,
-                                contents: NodeContent {
-                                    content: InnerNodeContent::Tokens(vec![counterfactual_token]),
-                                    metadata,
-                                    model,
-                                },
-                            });
-                        }
+                        add_counterfactual_sibling(weave, node, index, counterfactual_index);
                     }
                 } else {
                     render_node_metadata_tooltip(ui, node);
@@ -1001,3 +1141,47 @@ fn render_tooltip(ui: &mut Ui, weave: &mut WeaveWrapper, node: Ulid, index: usiz
         }
     }
 }
+
+// Splits out the token at the specified index, adding the chosen counterfactual token as a sibling of it
+fn add_counterfactual_sibling(
+    weave: &mut WeaveWrapper,
+    node: Ulid,
+    index: usize,
+    counterfactual_index: usize,
+) {
+    if let Some(node) = weave.get_node(&node)
+        && let InnerNodeContent::Tokens(tokens) = &node.contents.content
+        && let Some((_, token_metadata)) = tokens.get(index)
+        && let Some(value) = token_metadata.get("counterfactual").cloned()
+        && let Some(counterfactual) = deserialize_counterfactual_logprobs(&value)
+        && let Some(mut counterfactual_token) = counterfactual.get(counterfactual_index).cloned()
+    {
+        let metadata = node.contents.metadata.clone();
+        let model = node.contents.model.clone();
+
+        let node = Ulid(node.id);
+        if let Some(split) = weave.split_out_token(&node, index) {
+            let active = weave
+                .get_active_thread_u128()
+                .collect::<Vec<_>>()
+                .contains(&node.0);
+
+            counterfactual_token
+                .1
+                .insert("counterfactual".to_string(), value);
+
+            weave.add_node(TapestryNode {
+                id: Ulid::new().0,
+                from: split.0.map(|id| id.0),
+                to: IndexSet::default(),
+                active,
+                bookmarked: false,
+                contents: NodeContent {
+                    content: InnerNodeContent::Tokens(vec![counterfactual_token]),
+                    metadata,
+                    model,
+                },
+            });
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::mem;
+
+use eframe::egui::{Color32, Tooltip, Ui};
+use egui_notify::Toasts;
+use egui_plot::{Legend, Line, MarkerShape, Plot, PlotPoint, PlotPoints, Points, VLine};
+use flagset::FlagSet;
+use tapestry_weave::{ulid::Ulid, v0::InnerNodeContent};
+
+use crate::{
+    editor::shared::{
+        NodeIndex, SharedState, calculate_token_entropy, calculate_token_logprob,
+        render_token_tooltip, weave::WeaveWrapper,
+    },
+    settings::{Settings, shortcuts::Shortcuts},
+};
+
+// Tokens whose logprob is this many standard deviations below the thread's average are marked as spikes
+const SPIKE_DEVIATIONS: f32 = 2.0;
+const SPIKE_MINIMUM_TOKENS: usize = 8;
+
+// The maximum horizontal distance (in points) between the pointer and a token for the token to be selected
+const POINTER_TOLERANCE: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Series {
+    Logprob,
+    Entropy,
+    Confidence,
+}
+
+impl Series {
+    const ALL: [Self; 3] = [Self::Logprob, Self::Entropy, Self::Confidence];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Logprob => "Logprob",
+            Self::Entropy => "Entropy",
+            Self::Confidence => "Confidence",
+        }
+    }
+    fn color(&self, ui: &Ui) -> Color32 {
+        match self {
+            Self::Logprob => ui.visuals().selection.bg_fill,
+            Self::Entropy => ui.visuals().hyperlink_color,
+            Self::Confidence => ui.visuals().warn_fg_color,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TimelineToken {
+    node: Ulid,
+    index: usize,
+    node_offset: usize,
+    segment: usize,
+    position: f64,
+    values: [Option<f32>; 3],
+}
+
+#[derive(Debug, Default)]
+struct Timeline {
+    tokens: Vec<TimelineToken>,
+    lines: Vec<(Series, Vec<PlotPoint>)>,
+    spikes: Vec<PlotPoint>,
+    branches: Vec<f64>,
+    length: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct TimelineView {
+    timeline: Option<Timeline>,
+    follow_cursor: bool,
+}
+
+impl TimelineView {
+    /*pub fn reset(&mut self) {
+        self.timeline = None;
+    }*/
+    pub fn update(
+        &mut self,
+        _weave: &mut WeaveWrapper,
+        _settings: &Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        if state.has_weave_changed {
+            self.timeline = None;
+        }
+        if state.has_cursor_node_changed {
+            self.follow_cursor = true;
+        }
+    }
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        weave: &mut WeaveWrapper,
+        _settings: &mut Settings,
+        _toasts: &mut Toasts,
+        state: &mut SharedState,
+        _shortcuts: FlagSet<Shortcuts>,
+    ) {
+        let timeline = self.timeline.get_or_insert_with(|| build_timeline(weave));
+
+        let has_pointer = ui
+            .clip_rect()
+            .contains(ui.ctx().pointer_hover_pos().unwrap_or_default());
+
+        let cursor_position = match state.get_cursor_node() {
+            NodeIndex::WithinNode(node, offset) => timeline
+                .tokens
+                .iter()
+                .rev()
+                .find(|token| token.node == node && token.node_offset <= offset)
+                .map(|token| token.position),
+            _ => None,
+        };
+        let follow_cursor = self.follow_cursor && !has_pointer;
+        self.follow_cursor = false;
+
+        let spike_color = ui.visuals().error_fg_color;
+        let branch_color = ui.visuals().weak_text_color();
+        let cursor_color = ui.visuals().widgets.noninteractive.fg_stroke.color;
+        let colors = Series::ALL.map(|series| series.color(ui));
+
+        let mut pointer_token = None;
+
+        let response = Plot::new([state.identifier.to_string(), "timeline".to_string()])
+            .legend(Legend::default())
+            .show_x(false)
+            .show_y(false)
+            .allow_zoom([true, false])
+            .allow_scroll([true, false])
+            .allow_drag([true, false])
+            .include_y(0.0)
+            .include_x(0.0)
+            .include_x(timeline.length)
+            .x_axis_label("Byte offset")
+            .show(ui, |ui| {
+                for (series, points) in &timeline.lines {
+                    ui.add(
+                        Line::new(series.label(), PlotPoints::Borrowed(points))
+                            .color(colors[*series as usize])
+                            .allow_hover(false),
+                    );
+                }
+
+                if !timeline.spikes.is_empty() {
+                    ui.add(
+                        Points::new("Low probability", PlotPoints::Borrowed(&timeline.spikes))
+                            .shape(MarkerShape::Diamond)
+                            .radius(4.0)
+                            .color(spike_color),
+                    );
+                }
+
+                for branch in &timeline.branches {
+                    ui.add(VLine::new("Branch points", *branch).color(branch_color));
+                }
+
+                if let Some(position) = cursor_position {
+                    ui.add(
+                        VLine::new("Cursor", position)
+                            .color(cursor_color)
+                            .width(1.5),
+                    );
+
+                    if follow_cursor {
+                        let mut bounds = ui.plot_bounds();
+                        if !(bounds.min()[0]..=bounds.max()[0]).contains(&position) {
+                            bounds.set_x_center_width(position, bounds.width());
+                            ui.set_plot_bounds(bounds);
+                        }
+                    }
+                }
+
+                if let Some(pointer) = ui.pointer_coordinate()
+                    && !ui.response().dragged()
+                {
+                    let index = timeline
+                        .tokens
+                        .partition_point(|token| token.position < pointer.x);
+
+                    pointer_token = [index.checked_sub(1), Some(index)]
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|index| timeline.tokens.get(index).map(|token| (index, token)))
+                        .map(|(index, token)| {
+                            let distance = (ui
+                                .screen_from_plot(PlotPoint::new(token.position, pointer.y))
+                                .x
+                                - ui.screen_from_plot(pointer).x)
+                                .abs();
+
+                            (index, distance)
+                        })
+                        .filter(|(_, distance)| *distance <= POINTER_TOLERANCE)
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(index, _)| index);
+                }
+            });
+
+        if let Some(token) = pointer_token.and_then(|index| timeline.tokens.get(index)) {
+            let node_index = NodeIndex::WithinNode(token.node, token.node_offset);
+
+            state.set_hovered_node(node_index);
+
+            if response.response.clicked() {
+                state.set_cursor_node(node_index);
+            }
+
+            Tooltip::for_widget(&response.response)
+                .at_pointer()
+                .show(|ui| {
+                    if let Some(node) = weave.get_node(&token.node)
+                        && let InnerNodeContent::Tokens(tokens) = &node.contents.content
+                        && let Some((token_bytes, token_metadata)) = tokens.get(token.index)
+                    {
+                        render_token_tooltip(ui, token_bytes, token_metadata);
+
+                        if let Some(entropy) = token.values[Series::Entropy as usize] {
+                            ui.label(format!("entropy: {entropy:.2}"));
+                        }
+                    }
+                });
+        }
+    }
+}
+
+fn build_timeline(weave: &mut WeaveWrapper) -> Timeline {
+    let mut timeline = Timeline::default();
+
+    let active: Vec<u128> = weave.get_active_thread_u128().collect();
+
+    if weave.get_roots().nth(1).is_some() {
+        timeline.branches.push(0.0);
+    }
+
+    let mut offset = 0;
+    let mut segment = 0;
+
+    for node in active
+        .into_iter()
+        .rev()
+        .filter_map(|id| weave.get_node_u128(&id))
+    {
+        match &node.contents.content {
+            InnerNodeContent::Snippet(snippet) => {
+                offset += snippet.len();
+                segment += 1;
+            }
+            InnerNodeContent::Tokens(tokens) => {
+                let mut node_offset = 0;
+
+                for (index, (token, token_metadata)) in tokens.iter().enumerate() {
+                    timeline.tokens.push(TimelineToken {
+                        node: Ulid(node.id),
+                        index,
+                        node_offset,
+                        segment,
+                        position: offset as f64,
+                        values: [
+                            calculate_token_logprob(token_metadata),
+                            calculate_token_entropy(token_metadata),
+                            token_metadata
+                                .get("confidence")
+                                .and_then(|value| value.parse::<f32>().ok()),
+                        ],
+                    });
+
+                    node_offset += token.len();
+                    offset += token.len();
+                }
+            }
+        }
+
+        if node.to.len() > 1 {
+            timeline.branches.push(offset as f64);
+        }
+    }
+
+    timeline.length = offset as f64;
+
+    // Lines are split wherever a value is missing (such as within snippets), rather than interpolating across the gap
+    for series in Series::ALL {
+        let mut points = Vec::new();
+        let mut last_segment = 0;
+
+        for token in &timeline.tokens {
+            if token.segment != last_segment && !points.is_empty() {
+                timeline.lines.push((series, mem::take(&mut points)));
+            }
+            last_segment = token.segment;
+
+            if let Some(value) = token.values[series as usize] {
+                points.push(PlotPoint::new(token.position, value));
+            } else if !points.is_empty() {
+                timeline.lines.push((series, mem::take(&mut points)));
+            }
+        }
+
+        if !points.is_empty() {
+            timeline.lines.push((series, points));
+        }
+    }
+
+    let logprobs: Vec<f32> = timeline
+        .tokens
+        .iter()
+        .filter_map(|token| token.values[Series::Logprob as usize])
+        .collect();
+
+    if logprobs.len() >= SPIKE_MINIMUM_TOKENS {
+        let mean = logprobs.iter().sum::<f32>() / logprobs.len() as f32;
+        let deviation = (logprobs
+            .iter()
+            .map(|logprob| (logprob - mean).powi(2))
+            .sum::<f32>()
+            / logprobs.len() as f32)
+            .sqrt();
+        let threshold = mean - (deviation * SPIKE_DEVIATIONS);
+
+        timeline.spikes = timeline
+            .tokens
+            .iter()
+            .filter_map(|token| {
+                token.values[Series::Logprob as usize]
+                    .filter(|logprob| *logprob < threshold)
+                    .map(|logprob| PlotPoint::new(token.position, logprob))
+            })
+            .collect();
+    }
+
+    timeline
+}
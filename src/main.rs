@@ -30,7 +30,7 @@ use crate::{
     files::FileManager,
     settings::{
         Settings, UIFonts, UISettings,
-        inference::{ClientConfig, InferenceClient},
+        inference::{ClientConfig, InferenceCache, InferenceClient, SecretPrompt},
         shortcuts::Shortcuts,
     },
 };
@@ -93,6 +93,7 @@ struct TapestryLoomApp {
     first_frame: bool,
     dark_mode: bool,
     last_focused: Option<bool>,
+    secret_prompt: SecretPrompt,
 }
 
 impl TapestryLoomApp {
@@ -282,6 +283,7 @@ impl TapestryLoomApp {
             close_queue: Vec::with_capacity(8),
             settings,
             client: Rc::new(RefCell::new(client)),
+            cache: InferenceCache::new(&runtime),
             toasts,
             runtime,
             open_documents,
@@ -311,6 +313,7 @@ impl TapestryLoomApp {
             last_ui_settings,
             last_client_settings,
             last_focused: None,
+            secret_prompt: SecretPrompt::new(),
         }
     }
     fn allow_close(&self) -> bool {
@@ -422,6 +425,7 @@ impl App for TapestryLoomApp {
         self.behavior.settings_visible = false;
 
         settings.notices.display(ctx);
+        self.secret_prompt.display(ctx);
 
         if self
             .behavior
@@ -464,6 +468,7 @@ impl App for TapestryLoomApp {
 struct TapestryLoomBehavior {
     settings: Rc<RefCell<Settings>>,
     client: Rc<RefCell<Option<InferenceClient>>>,
+    cache: InferenceCache,
     toasts: Rc<RefCell<Toasts>>,
     runtime: Arc<Runtime>,
 
@@ -507,6 +512,7 @@ impl TapestryLoomBehavior {
                     self.open_documents.clone(),
                     self.runtime.clone(),
                     self.client.clone(),
+                    self.cache.clone(),
                     path,
                     Box::new(move |_| {
                         file_manager.borrow_mut().refresh();
@@ -559,7 +565,7 @@ impl Behavior<Pane> for TapestryLoomBehavior {
         match pane {
             Pane::Settings => {
                 self.settings_visible = true;
-                self.settings.borrow_mut().render(ui);
+                self.settings.borrow_mut().render(ui, &self.cache);
             }
             Pane::FileManager => {
                 self.file_manager
@@ -573,6 +579,9 @@ impl Behavior<Pane> for TapestryLoomBehavior {
                 || {
                     self.close_queue.push(tile_id);
                 },
+                |path| {
+                    self.new_editor_queue.push((Some(path), None));
+                },
                 self.pressed_shortcuts,
             ),
         }
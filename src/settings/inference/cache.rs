@@ -0,0 +1,259 @@
+use std::{
+    fs::{self, File},
+    hash::Hasher,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use fnv::FnvHasher;
+use log::{debug, warn};
+use tapestry_weave::ulid::Ulid;
+use tokio::task;
+use walkdir::WalkDir;
+
+// The cache directory is pruned on the first write of each session, and periodically afterwards
+const PRUNE_INTERVAL: usize = 1024;
+
+pub(super) struct DiskCache {
+    root: Option<PathBuf>,
+    max_size: u64,
+    writes: AtomicUsize,
+}
+
+pub(super) trait DiskCacheValue: Sized {
+    fn encode(&self, output: &mut Vec<u8>);
+    fn decode(input: &[u8]) -> Option<Self>;
+}
+
+impl DiskCacheValue for Vec<f32> {
+    fn encode(&self, output: &mut Vec<u8>) {
+        for value in self {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    fn decode(input: &[u8]) -> Option<Self> {
+        if !input.len().is_multiple_of(4) {
+            return None;
+        }
+
+        Some(
+            input
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        )
+    }
+}
+
+impl DiskCacheValue for Vec<u64> {
+    fn encode(&self, output: &mut Vec<u8>) {
+        for value in self {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    fn decode(input: &[u8]) -> Option<Self> {
+        if !input.len().is_multiple_of(8) {
+            return None;
+        }
+
+        Some(
+            input
+                .chunks_exact(8)
+                .map(|chunk| {
+                    u64::from_le_bytes([
+                        chunk[0], chunk[1], chunk[2], chunk[3], chunk[4], chunk[5], chunk[6],
+                        chunk[7],
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl DiskCache {
+    pub(super) fn new(name: &str, max_size: u64) -> Self {
+        let root = dirs_next::cache_dir().map(|path| path.join("tapestry-loom").join(name));
+
+        if root.is_none() {
+            warn!("Unable to locate cache directory, {name} cache will not be persisted");
+        }
+
+        Self {
+            root,
+            max_size,
+            writes: AtomicUsize::new(0),
+        }
+    }
+    fn path(&self, namespace: u64, content: &[u8]) -> Option<PathBuf> {
+        self.root.as_ref().map(|root| {
+            root.join(format!("{namespace:016x}"))
+                .join(format!("{:016x}", hash_key(&[content])))
+        })
+    }
+    pub(super) async fn get<T: DiskCacheValue + Send + 'static>(
+        &self,
+        namespace: u64,
+        content: &[u8],
+    ) -> Option<T> {
+        let path = self.path(namespace, content)?;
+        let content = content.to_vec();
+
+        match task::spawn_blocking(move || read_entry(&path, &content)).await {
+            Ok(value) => value,
+            Err(error) => {
+                warn!("Failed to read cache entry: {error:#?}");
+                None
+            }
+        }
+    }
+    pub(super) async fn insert<T: DiskCacheValue + Send + 'static>(
+        &self,
+        namespace: u64,
+        content: Vec<u8>,
+        value: T,
+    ) {
+        if let Some(path) = self.path(namespace, &content) {
+            let prune_root = if self
+                .writes
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(PRUNE_INTERVAL)
+            {
+                self.root.clone()
+            } else {
+                None
+            };
+            let max_size = self.max_size;
+
+            let result = task::spawn_blocking(move || {
+                let result = write_entry(&path, &content, &value);
+
+                if let Some(root) = prune_root {
+                    prune(&root, max_size);
+                }
+
+                result
+            })
+            .await;
+
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => warn!("Failed to write cache entry: {error:#?}"),
+                Err(error) => warn!("Failed to write cache entry: {error:#?}"),
+            }
+        }
+    }
+    pub(super) async fn clear(&self) {
+        if let Some(root) = self.root.clone() {
+            match task::spawn_blocking(move || fs::remove_dir_all(root)).await {
+                Ok(Ok(())) => debug!("Cleared cache directory"),
+                Ok(Err(error)) => {
+                    if error.kind() != ErrorKind::NotFound {
+                        warn!("Failed to clear cache directory: {error:#?}");
+                    }
+                }
+                Err(error) => warn!("Failed to clear cache directory: {error:#?}"),
+            }
+        }
+    }
+}
+
+pub(super) fn hash_key(parts: &[&[u8]]) -> u64 {
+    let mut hasher = FnvHasher::default();
+
+    for part in parts {
+        hasher.write(&(part.len() as u64).to_le_bytes());
+        hasher.write(part);
+    }
+
+    hasher.finish()
+}
+
+// Entries are stored as the length of the cached content, followed by the content itself (to detect hash collisions), followed by the cached value
+fn read_entry<T: DiskCacheValue>(path: &Path, content: &[u8]) -> Option<T> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) => {
+            if error.kind() != ErrorKind::NotFound {
+                warn!("Failed to read cache entry: {error:#?}");
+            }
+            return None;
+        }
+    };
+
+    let length = u64::from_le_bytes(data.get(..8)?.try_into().ok()?) as usize;
+
+    if data.get(8..8_usize.checked_add(length)?)? != content {
+        return None;
+    }
+
+    let value = T::decode(&data[8 + length..])?;
+
+    // Recently used entries are evicted last
+    if let Ok(file) = File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+
+    Some(value)
+}
+
+fn write_entry<T: DiskCacheValue>(
+    path: &Path,
+    content: &[u8],
+    value: &T,
+) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut data = Vec::with_capacity(8 + content.len());
+    data.extend_from_slice(&(content.len() as u64).to_le_bytes());
+    data.extend_from_slice(content);
+    value.encode(&mut data);
+
+    // Entries may be read by other instances of the application while they're being written
+    let temporary = path.with_extension(Ulid::new().to_string());
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, path)
+}
+
+fn prune(root: &Path, max_size: u64) {
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.into_path()))
+        })
+        .collect();
+
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+
+    if total <= max_size {
+        return;
+    }
+
+    entries.sort_unstable_by_key(|(modified, _, _)| *modified);
+
+    // Leave some headroom to avoid pruning on every write
+    let target = max_size - (max_size / 4);
+    let mut removed = 0;
+
+    for (_, size, path) in entries {
+        if total <= target {
+            break;
+        }
+
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                total -= size;
+                removed += 1;
+            }
+            Err(error) => warn!("Failed to remove cache entry: {error:#?}"),
+        }
+    }
+
+    debug!("Pruned {removed} entries from {}", root.display());
+}
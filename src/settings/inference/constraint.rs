@@ -0,0 +1,372 @@
+use std::{collections::HashSet, fmt::Display};
+
+use eframe::egui::{CollapsingHeader, ComboBox, RichText, TextEdit, Ui, Widget};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+    #[default]
+    Grammar,
+    JsonSchema,
+    Regex,
+}
+
+impl Display for ConstraintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Grammar => f.write_str("GBNF grammar"),
+            Self::JsonSchema => f.write_str("JSON schema"),
+            Self::Regex => f.write_str("Regex"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ConstraintStyle {
+    #[default]
+    LlamaCpp,
+    Vllm,
+    OpenAI,
+}
+
+impl Display for ConstraintStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LlamaCpp => f.write_str("llama.cpp"),
+            Self::Vllm => f.write_str("vLLM"),
+            Self::OpenAI => f.write_str("OpenAI"),
+        }
+    }
+}
+
+impl ConstraintStyle {
+    pub(super) fn render(&mut self, ui: &mut Ui) {
+        ComboBox::from_label("Constraint format")
+            .selected_text(self.to_string())
+            .show_ui(ui, |ui| {
+                for style in [Self::LlamaCpp, Self::Vllm, Self::OpenAI] {
+                    ui.selectable_value(self, style, style.to_string());
+                }
+            })
+            .response
+            .on_hover_text("The request fields used for constrained generation.\n\nllama.cpp: grammar and json_schema\nvLLM: guided_grammar, guided_json and guided_regex\nOpenAI: response_format (JSON schemas only)");
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub label: String,
+    pub kind: ConstraintKind,
+    pub source: String,
+}
+
+impl Constraint {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.kind {
+            ConstraintKind::Grammar => validate_grammar(&self.source),
+            ConstraintKind::JsonSchema => match serde_json::from_str::<Value>(&self.source) {
+                Ok(Value::Object(_)) => Ok(()),
+                Ok(_) => Err("JSON schemas must be objects".to_string()),
+                Err(error) => Err(error.to_string()),
+            },
+            ConstraintKind::Regex => Regex::new(&self.source)
+                .map(|_| ())
+                .map_err(|error| error.to_string()),
+        }
+    }
+    pub(super) fn render(&mut self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            let label = ui.label("Label:").id;
+            TextEdit::singleline(&mut self.label)
+                .hint_text("Constraint label")
+                .desired_width(ui.spacing().text_edit_width * 0.6)
+                .ui(ui)
+                .labelled_by(label);
+
+            ComboBox::from_id_salt(ui.next_auto_id())
+                .selected_text(self.kind.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        ConstraintKind::Grammar,
+                        ConstraintKind::JsonSchema,
+                        ConstraintKind::Regex,
+                    ] {
+                        ui.selectable_value(&mut self.kind, kind, kind.to_string());
+                    }
+                });
+        });
+
+        TextEdit::multiline(&mut self.source)
+            .hint_text(match self.kind {
+                ConstraintKind::Grammar => "root ::= ...",
+                ConstraintKind::JsonSchema => "{\"type\": \"object\", ...}",
+                ConstraintKind::Regex => "[A-Z][a-z]+",
+            })
+            .code_editor()
+            .desired_rows(4)
+            .desired_width(f32::INFINITY)
+            .ui(ui);
+
+        if let Err(error) = self.validate() {
+            ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+        }
+    }
+}
+
+pub(super) fn insert_constraint(
+    body: &mut Map<String, Value>,
+    constraint: &Constraint,
+    style: ConstraintStyle,
+) -> Result<(), anyhow::Error> {
+    constraint.validate().map_err(|error| {
+        anyhow::Error::msg(format!(
+            "Invalid {} \"{}\": {error}",
+            constraint.kind, constraint.label
+        ))
+    })?;
+
+    let source = Value::String(constraint.source.clone());
+
+    match (style, constraint.kind) {
+        (ConstraintStyle::LlamaCpp, ConstraintKind::Grammar) => {
+            body.insert("grammar".to_string(), source);
+        }
+        (ConstraintStyle::LlamaCpp, ConstraintKind::JsonSchema) => {
+            body.insert(
+                "json_schema".to_string(),
+                serde_json::from_str(&constraint.source)?,
+            );
+        }
+        (ConstraintStyle::Vllm, ConstraintKind::Grammar) => {
+            body.insert("guided_grammar".to_string(), source);
+        }
+        (ConstraintStyle::Vllm, ConstraintKind::JsonSchema) => {
+            body.insert(
+                "guided_json".to_string(),
+                serde_json::from_str(&constraint.source)?,
+            );
+        }
+        (ConstraintStyle::Vllm, ConstraintKind::Regex) => {
+            body.insert("guided_regex".to_string(), source);
+        }
+        (ConstraintStyle::OpenAI, ConstraintKind::JsonSchema) => {
+            let name: String = constraint
+                .label
+                .chars()
+                .filter(|char| char.is_ascii_alphanumeric() || *char == '_' || *char == '-')
+                .collect();
+
+            body.insert(
+                "response_format".to_string(),
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": if name.is_empty() { "constraint".to_string() } else { name },
+                        "schema": serde_json::from_str::<Value>(&constraint.source)?,
+                        "strict": true,
+                    },
+                }),
+            );
+        }
+        (style, kind) => {
+            return Err(anyhow::Error::msg(format!(
+                "{kind} constraints are not supported by the {style} constraint format"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+enum GrammarToken {
+    Name(String),
+    Define,
+    Open,
+    Close,
+    Other,
+}
+
+// Checks the structure of a llama.cpp GBNF grammar without fully parsing its expressions
+fn validate_grammar(source: &str) -> Result<(), String> {
+    let tokens = tokenize_grammar(source)?;
+
+    let mut defined = HashSet::new();
+    let mut referenced = Vec::new();
+    let mut rule: Option<&str> = None;
+    let mut depth: usize = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            GrammarToken::Name(name) => {
+                if matches!(tokens.get(index + 1), Some(GrammarToken::Define)) {
+                    if depth > 0
+                        && let Some(rule) = rule
+                    {
+                        return Err(format!("Unclosed group in rule \"{rule}\""));
+                    }
+
+                    defined.insert(name.as_str());
+                    rule = Some(name);
+                } else if rule.is_none() {
+                    return Err("Grammars must begin with a rule definition".to_string());
+                } else {
+                    referenced.push(name.as_str());
+                }
+            }
+            GrammarToken::Define => {
+                if !matches!(
+                    index.checked_sub(1).and_then(|index| tokens.get(index)),
+                    Some(GrammarToken::Name(_))
+                ) {
+                    return Err("\"::=\" must follow a rule name".to_string());
+                }
+            }
+            GrammarToken::Open => depth += 1,
+            GrammarToken::Close => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    format!("Unexpected \")\" in rule \"{}\"", rule.unwrap_or_default())
+                })?;
+            }
+            GrammarToken::Other => {
+                if rule.is_none() {
+                    return Err("Grammars must begin with a rule definition".to_string());
+                }
+            }
+        }
+    }
+
+    if depth > 0 {
+        return Err(format!(
+            "Unclosed group in rule \"{}\"",
+            rule.unwrap_or_default()
+        ));
+    }
+
+    if !defined.contains("root") {
+        return Err("Grammars must define a \"root\" rule".to_string());
+    }
+
+    if let Some(name) = referenced.into_iter().find(|name| !defined.contains(name)) {
+        return Err(format!("Undefined rule \"{name}\""));
+    }
+
+    Ok(())
+}
+
+fn tokenize_grammar(source: &str) -> Result<Vec<GrammarToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            char if char.is_whitespace() => {}
+            '#' => {
+                for char in chars.by_ref() {
+                    if char == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' | '[' => {
+                let end = if char == '"' { '"' } else { ']' };
+                let mut terminated = false;
+
+                while let Some(char) = chars.next() {
+                    if char == '\\' {
+                        chars.next();
+                    } else if char == end {
+                        terminated = true;
+                        break;
+                    }
+                }
+
+                if !terminated {
+                    return Err(if end == '"' {
+                        "Unterminated string literal".to_string()
+                    } else {
+                        "Unterminated character class".to_string()
+                    });
+                }
+
+                tokens.push(GrammarToken::Other);
+            }
+            '{' => {
+                if !chars.by_ref().any(|char| char == '}') {
+                    return Err("Unterminated repetition".to_string());
+                }
+
+                tokens.push(GrammarToken::Other);
+            }
+            ':' => {
+                if chars.next() == Some(':') && chars.next() == Some('=') {
+                    tokens.push(GrammarToken::Define);
+                } else {
+                    return Err("Expected \"::=\"".to_string());
+                }
+            }
+            '(' => tokens.push(GrammarToken::Open),
+            ')' => tokens.push(GrammarToken::Close),
+            '|' | '*' | '+' | '?' | '.' => tokens.push(GrammarToken::Other),
+            char if char.is_ascii_alphanumeric() || char == '-' || char == '_' => {
+                let mut name = String::from(char);
+
+                while let Some(char) = chars
+                    .next_if(|char| char.is_ascii_alphanumeric() || *char == '-' || *char == '_')
+                {
+                    name.push(char);
+                }
+
+                tokens.push(GrammarToken::Name(name));
+            }
+            char => return Err(format!("Unexpected character {char:?}")),
+        }
+    }
+
+    Ok(tokens)
+}
+
+pub(super) fn render_constraint_selector(
+    ui: &mut Ui,
+    constraint: &mut Option<Constraint>,
+    presets: &[Constraint],
+) {
+    CollapsingHeader::new("Constraint")
+        .id_salt(ui.next_auto_id())
+        .default_open(constraint.is_some())
+        .show(ui, |ui| {
+            let selected = match constraint {
+                Some(constraint) if !constraint.label.is_empty() => constraint.label.clone(),
+                Some(_) => "Custom".to_string(),
+                None => "None".to_string(),
+            };
+
+            ComboBox::from_id_salt(ui.next_auto_id())
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(constraint.is_none(), "None").clicked() {
+                        *constraint = None;
+                    }
+
+                    for preset in presets {
+                        if ui
+                            .selectable_label(constraint.as_ref() == Some(preset), &preset.label)
+                            .clicked()
+                        {
+                            *constraint = Some(preset.clone());
+                        }
+                    }
+
+                    if ui.selectable_label(false, "Custom").clicked() {
+                        *constraint = Some(Constraint::default());
+                    }
+                });
+
+            if let Some(constraint) = constraint {
+                constraint.render(ui);
+            }
+        })
+        .header_response
+        .on_hover_text("Restricts generated text to a grammar, JSON schema, or regex. Constraints are validated before each request, and are sent using the constraint format configured for each model.");
+}
@@ -0,0 +1,253 @@
+use eframe::egui::{CollapsingHeader, DragValue, TextEdit, Ui, Widget};
+use serde::{Deserialize, Serialize};
+use tapestry_weave::ulid::Ulid;
+
+use super::TokensOrBytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentSource {
+    Human,
+    Model,
+    Context,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ContextParameters {
+    pub memory: String,
+    pub authors_note: String,
+    pub authors_note_depth: usize,
+}
+
+impl ContextParameters {
+    pub(super) fn render(&mut self, ui: &mut Ui) {
+        CollapsingHeader::new("Context")
+            .id_salt(ui.next_auto_id())
+            .default_open(!(self.memory.is_empty() && self.authors_note.is_empty()))
+            .show(ui, |ui| {
+                let label = ui.label("Memory:").id;
+                TextEdit::multiline(&mut self.memory)
+                    .hint_text("Text pinned to the start of the context")
+                    .desired_rows(2)
+                    .ui(ui)
+                    .labelled_by(label);
+
+                let label = ui.label("Author's note:").id;
+                TextEdit::multiline(&mut self.authors_note)
+                    .hint_text("[Author's note: ...]")
+                    .desired_rows(2)
+                    .ui(ui)
+                    .labelled_by(label);
+
+                ui.horizontal_wrapped(|ui| {
+                    let label = ui.label("Author's note depth:").id;
+                    ui.add(DragValue::new(&mut self.authors_note_depth).suffix(" lines"))
+                        .labelled_by(label)
+                        .on_hover_text("The number of lines from the end of the context at which the author's note is inserted.");
+                });
+            });
+    }
+    fn is_empty(&self) -> bool {
+        self.memory.is_empty() && self.authors_note.is_empty()
+    }
+}
+
+pub(super) fn estimate_token_count(content: &TokensOrBytes, model_id: &Ulid) -> usize {
+    match content {
+        TokensOrBytes::TokensAndBytes(tokens)
+            if tokens
+                .iter()
+                .all(|(_, _, token_model)| token_model == model_id) =>
+        {
+            tokens.len()
+        }
+        TokensOrBytes::TokensAndBytes(tokens) => tokens
+            .iter()
+            .map(|(token, _, _)| token.len())
+            .sum::<usize>()
+            .div_ceil(4),
+        TokensOrBytes::Bytes(bytes) => bytes.len().div_ceil(4),
+    }
+}
+
+pub(super) async fn build_context(
+    content: &[TokensOrBytes],
+    sources: &[SegmentSource],
+    parameters: &ContextParameters,
+    budget: Option<usize>,
+    mut count_tokens: impl AsyncFnMut(TokensOrBytes) -> Result<usize, anyhow::Error>,
+) -> Result<(Vec<TokensOrBytes>, Vec<SegmentSource>), anyhow::Error> {
+    let mut sources: Vec<SegmentSource> = (0..content.len())
+        .map(|index| sources.get(index).copied().unwrap_or(SegmentSource::Human))
+        .collect();
+
+    if budget.is_none() && parameters.is_empty() {
+        return Ok((content.to_vec(), sources));
+    }
+
+    let mut segments = content.to_vec();
+
+    let memory = if parameters.memory.is_empty() {
+        None
+    } else {
+        Some(TokensOrBytes::Bytes(parameters.memory.as_bytes().to_vec()))
+    };
+
+    let note = if parameters.authors_note.is_empty() {
+        None
+    } else {
+        Some(parameters.authors_note.as_bytes().to_vec())
+    };
+
+    if let Some(budget) = budget {
+        let mut total = 0;
+
+        if let Some(memory) = &memory {
+            total += count_tokens(memory.clone()).await?;
+        }
+
+        if let Some(note) = &note {
+            total += count_tokens(TokensOrBytes::Bytes(note.clone())).await?;
+        }
+
+        if total > budget {
+            return Err(anyhow::Error::msg(
+                "Memory and author's note do not fit within the model's context length",
+            ));
+        }
+
+        let mut counts = Vec::with_capacity(segments.len());
+
+        for segment in &segments {
+            let count = count_tokens(segment.clone()).await?;
+            total += count;
+            counts.push(count);
+        }
+
+        let mut start = 0;
+
+        while total > budget && start < segments.len() {
+            let excess = total - budget;
+
+            if counts[start] <= excess {
+                total -= counts[start];
+                start += 1;
+            } else {
+                segments[start] = trim_front(
+                    std::mem::replace(&mut segments[start], TokensOrBytes::Bytes(Vec::new())),
+                    excess,
+                    counts[start],
+                );
+
+                if is_segment_empty(&segments[start]) {
+                    total -= counts[start];
+                    start += 1;
+                } else {
+                    let count = count_tokens(segments[start].clone()).await?;
+                    total = total - counts[start] + count;
+                    counts[start] = count;
+                }
+            }
+        }
+
+        segments.drain(..start);
+        sources.drain(..start);
+    }
+
+    // The author's note is inserted after truncation so that it can't be cut off
+    if let Some(note) = note {
+        insert_at_depth(
+            &mut segments,
+            &mut sources,
+            parameters.authors_note_depth,
+            note,
+        );
+    }
+
+    if let Some(memory) = memory {
+        segments.insert(0, memory);
+        sources.insert(0, SegmentSource::Context);
+    }
+
+    Ok((segments, sources))
+}
+
+fn is_segment_empty(segment: &TokensOrBytes) -> bool {
+    match segment {
+        TokensOrBytes::TokensAndBytes(tokens) => tokens.is_empty(),
+        TokensOrBytes::Bytes(bytes) => bytes.is_empty(),
+    }
+}
+
+fn trim_front(segment: TokensOrBytes, excess: usize, count: usize) -> TokensOrBytes {
+    match segment {
+        TokensOrBytes::TokensAndBytes(mut tokens) => {
+            tokens.drain(..excess.max(1).min(tokens.len()));
+            TokensOrBytes::TokensAndBytes(tokens)
+        }
+        TokensOrBytes::Bytes(mut bytes) => {
+            let mut index = (bytes.len() * excess)
+                .div_ceil(count.max(1))
+                .max(1)
+                .min(bytes.len());
+
+            // Avoid starting the context in the middle of a UTF-8 sequence
+            while index < bytes.len() && (bytes[index] & 0b1100_0000) == 0b1000_0000 {
+                index += 1;
+            }
+
+            bytes.drain(..index);
+            TokensOrBytes::Bytes(bytes)
+        }
+    }
+}
+
+fn insert_at_depth(
+    segments: &mut Vec<TokensOrBytes>,
+    sources: &mut Vec<SegmentSource>,
+    depth: usize,
+    note: Vec<u8>,
+) {
+    if depth == 0 {
+        segments.push(TokensOrBytes::Bytes(note));
+        sources.push(SegmentSource::Context);
+        return;
+    }
+
+    let mut lines = 0;
+
+    for index in (0..segments.len()).rev() {
+        let position = match &segments[index] {
+            TokensOrBytes::Bytes(bytes) => bytes.iter().rposition(|byte| {
+                if *byte == b'\n' {
+                    lines += 1;
+                }
+                lines >= depth
+            }),
+            TokensOrBytes::TokensAndBytes(tokens) => tokens.iter().rposition(|(token, _, _)| {
+                lines += token.iter().filter(|byte| **byte == b'\n').count();
+                lines >= depth
+            }),
+        };
+
+        if let Some(position) = position {
+            let tail = match &mut segments[index] {
+                TokensOrBytes::Bytes(bytes) => TokensOrBytes::Bytes(bytes.split_off(position + 1)),
+                TokensOrBytes::TokensAndBytes(tokens) => {
+                    TokensOrBytes::TokensAndBytes(tokens.split_off(position + 1))
+                }
+            };
+
+            if !is_segment_empty(&tail) {
+                segments.insert(index + 1, tail);
+                sources.insert(index + 1, sources[index]);
+            }
+            segments.insert(index + 1, TokensOrBytes::Bytes(note));
+            sources.insert(index + 1, SegmentSource::Context);
+
+            return;
+        }
+    }
+
+    segments.insert(0, TokensOrBytes::Bytes(note));
+    sources.insert(0, SegmentSource::Context);
+}
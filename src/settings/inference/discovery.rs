@@ -0,0 +1,367 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use eframe::egui::{Button, ComboBox, RichText, Spinner, Ui, Widget};
+use poll_promise::Promise;
+use reqwest::{Method, Url, header::HeaderMap};
+use serde_json::{Map, Value};
+use tapestry_weave::ulid::Ulid;
+
+use super::{
+    ClientConfig, Endpoint, EndpointConfig, InferenceCache, InferenceClient,
+    constraint::ConstraintStyle,
+    shared::{build_headers, error_for_status},
+};
+
+#[derive(Debug, Clone)]
+pub(super) struct DiscoveredModel {
+    pub(super) id: String,
+    pub(super) owned_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct ProbeReport {
+    pub(super) server: Option<String>,
+    pub(super) logprobs: Option<bool>,
+    pub(super) echo: Option<bool>,
+    pub(super) suffix: Option<bool>,
+    pub(super) token_prompts: Option<bool>,
+}
+
+impl ProbeReport {
+    pub(super) fn constraint_style(&self) -> Option<ConstraintStyle> {
+        match self.server.as_deref()? {
+            "vllm" => Some(ConstraintStyle::Vllm),
+            "llamacpp" => Some(ConstraintStyle::LlamaCpp),
+            "openai" | "system" => Some(ConstraintStyle::OpenAI),
+            _ => None,
+        }
+    }
+    fn render(&self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            if let Some(server) = &self.server {
+                ui.label(format!("Owned by: {server}"))
+                    .on_hover_text("The owned_by field reported by the endpoint's model list, which is used to guess the server software.");
+            }
+
+            for (label, supported, description) in [
+                (
+                    "Logprobs",
+                    self.logprobs,
+                    "Whether responses include token logprobs when requested.",
+                ),
+                (
+                    "Echo",
+                    self.echo,
+                    "Whether the endpoint can return the prompt along with the generated text.",
+                ),
+                (
+                    "Suffix",
+                    self.suffix,
+                    "Whether the endpoint accepts a suffix for fill-in-the-middle requests. Some servers accept the parameter but ignore it.",
+                ),
+                (
+                    "Token ID prompts",
+                    self.token_prompts,
+                    "Whether the endpoint accepts prompts made of token IDs rather than text.",
+                ),
+            ] {
+                if let Some(supported) = supported {
+                    let text = if supported {
+                        RichText::new(format!("\u{E06C} {label}"))
+                    } else {
+                        RichText::new(format!("\u{E1B2} {label}"))
+                            .color(ui.visuals().warn_fg_color)
+                    };
+
+                    ui.label(text).on_hover_text(description);
+                }
+            }
+        });
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Discovery {
+    models: Option<Promise<Result<Vec<DiscoveredModel>, anyhow::Error>>>,
+    probes: HashMap<Ulid, Promise<Result<ProbeReport, anyhow::Error>>>,
+}
+
+impl Debug for Discovery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Discovery").finish_non_exhaustive()
+    }
+}
+
+impl Discovery {
+    pub(super) fn render_models(
+        &mut self,
+        ui: &mut Ui,
+        config: &ClientConfig,
+        cache: &InferenceCache,
+        connection: Option<(String, String)>,
+    ) -> Option<String> {
+        let mut selected = None;
+
+        ui.horizontal_wrapped(|ui| {
+            let loading = self
+                .models
+                .as_ref()
+                .is_some_and(|models| models.ready().is_none());
+
+            if Button::new("\u{E145} Fetch models")
+                .ui(ui)
+                .on_hover_text("Lists the models served by the endpoint, using the OpenAI-style /v1/models route (or Ollama's /api/tags route as a fallback).")
+                .clicked()
+                && !loading
+                && let Some((endpoint, api_key)) = connection
+            {
+                self.models = Some(spawn(config, cache, move |client| async move {
+                    let headers = if api_key.is_empty() {
+                        HeaderMap::new()
+                    } else {
+                        build_headers(&[(
+                            "Authorization".to_string(),
+                            ["Bearer ", &api_key].concat(),
+                        )])?
+                    };
+
+                    list_models(&client, &endpoint, &headers).await
+                }));
+            }
+
+            match self.models.as_ref().map(Promise::ready) {
+                Some(None) => {
+                    ui.add(Spinner::new());
+                }
+                Some(Some(Ok(models))) => {
+                    ComboBox::from_id_salt("endpoint_template_models")
+                        .selected_text(format!("{} models found", models.len()))
+                        .show_ui(ui, |ui| {
+                            for model in models {
+                                let label = ui.selectable_label(false, &model.id);
+
+                                let label = if let Some(owned_by) = &model.owned_by {
+                                    label.on_hover_text(format!("Owned by: {owned_by}"))
+                                } else {
+                                    label
+                                };
+
+                                if label.clicked() {
+                                    selected = Some(model.id.clone());
+                                }
+                            }
+                        });
+                }
+                Some(Some(Err(error))) => {
+                    ui.label(
+                        RichText::new(format!("\u{E193} {error:#}"))
+                            .color(ui.visuals().warn_fg_color),
+                    );
+                }
+                None => {}
+            }
+        });
+
+        selected
+    }
+    pub(super) fn render_probe(
+        &mut self,
+        ui: &mut Ui,
+        id: &Ulid,
+        endpoint: &mut EndpointConfig,
+        config: &ClientConfig,
+        cache: &InferenceCache,
+    ) {
+        let mut apply = false;
+
+        ui.horizontal_wrapped(|ui| {
+            let testing = self
+                .probes
+                .get(id)
+                .is_some_and(|probe| probe.ready().is_none());
+
+            if Button::new("\u{E45C} Test connection")
+                .ui(ui)
+                .on_hover_text("Sends a few single-token requests to the endpoint to check which features it supports.\n\nThe results can be used to preconfigure the model's non-standard API modifications.")
+                .clicked()
+                && !testing
+            {
+                let endpoint = endpoint.clone();
+                let model = *id;
+
+                self.probes.insert(
+                    *id,
+                    spawn(config, cache, move |client| async move {
+                        endpoint.probe(&client.for_model(&model)).await
+                    }),
+                );
+            }
+
+            match self.probes.get(id).map(Promise::ready) {
+                Some(None) => {
+                    ui.add(Spinner::new());
+                }
+                Some(Some(Ok(_))) => {
+                    apply = ui
+                        .button("Apply suggested settings")
+                        .on_hover_text("Disables token ID prompts if they were rejected, and picks a constraint format based on the detected server.")
+                        .clicked();
+                }
+                Some(Some(Err(error))) => {
+                    ui.label(
+                        RichText::new(format!("\u{E193} {error:#}"))
+                            .color(ui.visuals().warn_fg_color),
+                    );
+                }
+                None => {}
+            }
+        });
+
+        if let Some(Some(Ok(report))) = self.probes.get(id).map(Promise::ready) {
+            report.render(ui);
+
+            if apply {
+                endpoint.apply_probe(report);
+            }
+        }
+    }
+}
+
+fn spawn<T, F>(
+    config: &ClientConfig,
+    cache: &InferenceCache,
+    request: impl FnOnce(InferenceClient) -> F,
+) -> Promise<Result<T, anyhow::Error>>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T, anyhow::Error>> + Send + 'static,
+{
+    match config.build() {
+        Ok(client) => {
+            let _guard = cache.runtime.enter();
+
+            Promise::spawn_async(request(client))
+        }
+        Err(error) => Promise::from_ready(Err(error)),
+    }
+}
+
+fn base_url(endpoint: &str) -> &str {
+    let mut base = endpoint.trim_end_matches('/');
+
+    for suffix in ["/chat/completions", "/completions", "/embeddings"] {
+        if let Some(stripped) = base.strip_suffix(suffix) {
+            base = stripped;
+            break;
+        }
+    }
+
+    base.strip_suffix("/v1").unwrap_or(base)
+}
+
+pub(super) async fn list_models(
+    client: &InferenceClient,
+    endpoint: &str,
+    headers: &HeaderMap,
+) -> Result<Vec<DiscoveredModel>, anyhow::Error> {
+    let base = base_url(endpoint);
+    let mut first_error = None;
+
+    for route in ["/v1/models", "/api/tags"] {
+        match get_json(client, &[base, route].concat(), headers).await {
+            Ok(response) => {
+                let models = parse_model_list(&response);
+
+                if !models.is_empty() {
+                    return Ok(models);
+                }
+            }
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| anyhow::Error::msg("Endpoint did not list any models")))
+}
+
+fn parse_model_list(response: &Value) -> Vec<DiscoveredModel> {
+    if let Some(data) = response.get("data").and_then(Value::as_array) {
+        data.iter()
+            .filter_map(|model| {
+                Some(DiscoveredModel {
+                    id: model.get("id")?.as_str()?.to_string(),
+                    owned_by: model
+                        .get("owned_by")
+                        .and_then(Value::as_str)
+                        .map(|owned_by| owned_by.to_string()),
+                })
+            })
+            .collect()
+    } else if let Some(models) = response.get("models").and_then(Value::as_array) {
+        models
+            .iter()
+            .filter_map(|model| {
+                Some(DiscoveredModel {
+                    id: model.get("name")?.as_str()?.to_string(),
+                    owned_by: Some("ollama".to_string()),
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+pub(super) async fn detect_server(
+    client: &InferenceClient,
+    endpoint: &str,
+    headers: &HeaderMap,
+    model: &str,
+) -> Option<String> {
+    let models = list_models(client, endpoint, headers).await.ok()?;
+
+    models
+        .iter()
+        .find(|item| item.id == model)
+        .or(models.first())
+        .and_then(|item| item.owned_by.clone())
+}
+
+async fn get_json(
+    client: &InferenceClient,
+    url: &str,
+    headers: &HeaderMap,
+) -> Result<Value, anyhow::Error> {
+    Ok(error_for_status(
+        client
+            .client
+            .request(Method::GET, Url::parse(url)?)
+            .headers(headers.clone())
+            .send()
+            .await?,
+    )
+    .await?
+    .json()
+    .await?)
+}
+
+pub(super) async fn post_json(
+    client: &InferenceClient,
+    endpoint: &str,
+    headers: &HeaderMap,
+    body: Map<String, Value>,
+) -> Result<Value, anyhow::Error> {
+    Ok(error_for_status(
+        client
+            .client
+            .request(Method::POST, Url::parse(endpoint)?)
+            .headers(headers.clone())
+            .json(&body)
+            .send()
+            .await?,
+    )
+    .await?
+    .json()
+    .await?)
+}
@@ -1,30 +1,72 @@
-use std::fmt::Display;
+use std::{borrow::Cow, collections::HashSet, fmt::Display, sync::Arc};
 
-use eframe::egui::{CollapsingHeader, TextEdit, Ui, Widget};
-use log::trace;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use eframe::egui::{CollapsingHeader, RichText, TextEdit, Ui, Widget};
+use log::{trace, warn};
 use reqwest::{
-    Method, Url,
-    header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+    Method, StatusCode, Url,
+    header::{CONTENT_TYPE, HeaderMap},
 };
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Number, Value};
+use serde_json::{Map, Number, Value, json};
 use tapestry_weave::ulid::Ulid;
+use tokenizers::Tokenizer;
+use tokio::{fs, task};
 
 use super::{
-    EMBEDDING_CACHE_MAX_SIZE, EmbeddingEndpoint, Endpoint, EndpointRequest, EndpointResponse,
-    InferenceCache, InferenceClient, RequestTokensOrBytes, Template, render_config_list,
-    render_config_map,
+    EmbeddingEndpoint, Endpoint, EndpointRequest, EndpointResponse, InferenceCache,
+    InferenceClient, RequestTokensOrBytes, Template, TokensOrBytes,
+    cache::hash_key,
+    constraint::{ConstraintStyle, insert_constraint},
+    context::{SegmentSource, estimate_token_count},
+    discovery::{ProbeReport, detect_server, post_json},
+    render_config_list, render_config_map, render_header_map,
+    sampler::{self, CHAT_COMPLETIONS_PARAMETERS, COMPLETIONS_PARAMETERS, ParameterSpec},
+    secrets,
     shared::{
-        build_json_list, build_json_object, error_for_status, parse_embedding_response,
-        parse_response, response_schema_error,
+        ResponseOptions, build_headers, build_json_list, build_json_object, error_for_status,
+        parse_embedding_response, response_schema_error, send_request,
     },
 };
 
+fn render_api_key_options(ui: &mut Ui, api_key: &str, save_as_secret: &mut bool) {
+    if !api_key.is_empty() && !api_key.contains("${") {
+        ui.checkbox(save_as_secret, "Save API key as a secret")
+            .on_hover_text("Adds the API key to the secret store, so that the endpoint's headers only contain a reference to it instead of the key itself.\n\nThe secret store must be unlocked to add the model.");
+
+        if !*save_as_secret {
+            ui.weak("The API key will be stored in plain text in the settings file.");
+        } else if !secrets::is_unlocked() {
+            ui.label(
+                RichText::new("The secret store is locked. Unlock or create it in the Secrets section before adding the model.")
+                    .color(ui.visuals().warn_fg_color),
+            );
+        }
+    }
+}
+
+// Returns None if the key should be saved as a secret but the store is locked or can't be saved, as the key shouldn't end up in plain text
+fn authorization_header(api_key: &str, save_as_secret: bool) -> Option<String> {
+    if !save_as_secret || api_key.contains("${") {
+        return Some(["Bearer ", api_key].concat());
+    }
+
+    match secrets::insert("API_KEY", api_key) {
+        Ok(Some(reference)) => Some(["Bearer ", &reference].concat()),
+        Ok(None) => None,
+        Err(error) => {
+            warn!("Unable to save API key as a secret: {error:#}");
+            None
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub(super) struct OpenAICompletionsTemplate {
     endpoint: String,
     model: String,
     api_key: String,
+    save_api_key: bool,
 }
 
 impl Template<OpenAICompletionsConfig> for OpenAICompletionsTemplate {
@@ -41,10 +83,25 @@ impl Template<OpenAICompletionsConfig> for OpenAICompletionsTemplate {
                 .on_hover_text("Model");
             TextEdit::singleline(&mut self.api_key)
                 .hint_text("API key (optional)")
+                .password(true)
                 .desired_width(ui.spacing().text_edit_width / 1.5)
                 .ui(ui)
-                .on_hover_text("API key");
+                .on_hover_text("API key\n\nA reference to a stored secret or environment variable (e.g. ${OPENAI_API_KEY}) can be used instead of the key itself.");
         });
+        render_api_key_options(ui, &self.api_key, &mut self.save_api_key);
+    }
+    fn connection(&self) -> (String, String) {
+        (
+            if self.endpoint.is_empty() {
+                "http://127.0.0.1:8080".to_string()
+            } else {
+                self.endpoint.clone()
+            },
+            self.api_key.clone(),
+        )
+    }
+    fn set_model(&mut self, model: String) {
+        self.model = model;
     }
     fn build(mut self) -> Option<OpenAICompletionsConfig> {
         Some(OpenAICompletionsConfig {
@@ -90,7 +147,7 @@ impl Template<OpenAICompletionsConfig> for OpenAICompletionsTemplate {
                 vec![
                     (
                         "Authorization".to_string(),
-                        ["Bearer ", &self.api_key].concat(),
+                        authorization_header(&self.api_key, self.save_api_key)?,
                     ),
                     ("User-Agent".to_string(), "TapestryLoom".to_string()),
                     (
@@ -110,6 +167,7 @@ pub(super) struct TapestryTokenizeOpenAICompletionsTemplate {
     endpoint: String,
     model: String,
     api_key: String,
+    save_api_key: bool,
 
     tokenization_endpoint: String,
 }
@@ -128,10 +186,12 @@ impl Template<OpenAICompletionsConfig> for TapestryTokenizeOpenAICompletionsTemp
                 .on_hover_text("Model");
             TextEdit::singleline(&mut self.api_key)
                 .hint_text("API key (optional)")
+                .password(true)
                 .desired_width(ui.spacing().text_edit_width / 1.5)
                 .ui(ui)
-                .on_hover_text("API key");
+                .on_hover_text("API key\n\nA reference to a stored secret or environment variable (e.g. ${OPENAI_API_KEY}) can be used instead of the key itself.");
         });
+        render_api_key_options(ui, &self.api_key, &mut self.save_api_key);
         ui.horizontal_wrapped(|ui| {
             TextEdit::singleline(&mut self.tokenization_endpoint)
                 .hint_text("http://127.0.0.1:8000")
@@ -139,6 +199,19 @@ impl Template<OpenAICompletionsConfig> for TapestryTokenizeOpenAICompletionsTemp
                 .on_hover_text("Tokenization base URL");
         });
     }
+    fn connection(&self) -> (String, String) {
+        (
+            if self.endpoint.is_empty() {
+                "http://127.0.0.1:8080".to_string()
+            } else {
+                self.endpoint.clone()
+            },
+            self.api_key.clone(),
+        )
+    }
+    fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
     fn build(mut self) -> Option<OpenAICompletionsConfig> {
         if self.model.is_empty() {
             return None;
@@ -157,6 +230,7 @@ impl Template<OpenAICompletionsConfig> for TapestryTokenizeOpenAICompletionsTemp
                 ]
                 .concat(),
                 reuse_tokens: true,
+                local_tokenization: true,
                 ..Default::default()
             },
             endpoint: if self.endpoint.is_empty() {
@@ -197,7 +271,7 @@ impl Template<OpenAICompletionsConfig> for TapestryTokenizeOpenAICompletionsTemp
                 vec![
                     (
                         "Authorization".to_string(),
-                        ["Bearer ", &self.api_key].concat(),
+                        authorization_header(&self.api_key, self.save_api_key)?,
                     ),
                     ("User-Agent".to_string(), "TapestryLoom".to_string()),
                     (
@@ -216,6 +290,7 @@ pub(super) struct OpenAIChatCompletionsTemplate {
     endpoint: String,
     model: String,
     api_key: String,
+    save_api_key: bool,
 }
 
 impl Template<OpenAIChatCompletionsConfig> for OpenAIChatCompletionsTemplate {
@@ -232,10 +307,25 @@ impl Template<OpenAIChatCompletionsConfig> for OpenAIChatCompletionsTemplate {
                 .on_hover_text("Model");
             TextEdit::singleline(&mut self.api_key)
                 .hint_text("API key (optional)")
+                .password(true)
                 .desired_width(ui.spacing().text_edit_width / 1.5)
                 .ui(ui)
-                .on_hover_text("API key");
+                .on_hover_text("API key\n\nA reference to a stored secret or environment variable (e.g. ${OPENAI_API_KEY}) can be used instead of the key itself.");
         });
+        render_api_key_options(ui, &self.api_key, &mut self.save_api_key);
+    }
+    fn connection(&self) -> (String, String) {
+        (
+            if self.endpoint.is_empty() {
+                "http://127.0.0.1:8080".to_string()
+            } else {
+                self.endpoint.clone()
+            },
+            self.api_key.clone(),
+        )
+    }
+    fn set_model(&mut self, model: String) {
+        self.model = model;
     }
     fn build(mut self) -> Option<OpenAIChatCompletionsConfig> {
         Some(OpenAIChatCompletionsConfig {
@@ -266,6 +356,10 @@ impl Template<OpenAIChatCompletionsConfig> for OpenAIChatCompletionsTemplate {
             prefix_messages: Vec::new(),
             message_role: "assistant".to_string(),
             suffix_messages: Vec::new(),
+            map_turns: false,
+            human_role: default_human_role(),
+            context_role: default_context_role(),
+            system_prompt: String::new(),
             parameters: if self.model.is_empty() {
                 Vec::new()
             } else {
@@ -284,7 +378,7 @@ impl Template<OpenAIChatCompletionsConfig> for OpenAIChatCompletionsTemplate {
                 vec![
                     (
                         "Authorization".to_string(),
-                        ["Bearer ", &self.api_key].concat(),
+                        authorization_header(&self.api_key, self.save_api_key)?,
                     ),
                     ("User-Agent".to_string(), "TapestryLoom".to_string()),
                     (
@@ -309,6 +403,251 @@ pub(super) struct OpenAICompletionsConfig {
     pub(super) nonstandard: NonStandardOpenAIModifications,
 }
 
+impl OpenAICompletionsConfig {
+    fn build_headers(&self) -> Result<HeaderMap, anyhow::Error> {
+        build_headers(&self.headers)
+    }
+    async fn load_tokenizer(
+        &self,
+        client: &InferenceClient,
+        headers: &HeaderMap,
+    ) -> Result<Tokenizer, anyhow::Error> {
+        let path = &self.nonstandard.tokenizer_path;
+
+        let contents =
+            if !path.is_empty() && !(path.starts_with("http://") || path.starts_with("https://")) {
+                fs::read(path).await?
+            } else {
+                let url = if path.is_empty() {
+                    [
+                        self.nonstandard.tokenization_endpoint.trim_end_matches('/'),
+                        "/tokenizer.json",
+                    ]
+                    .concat()
+                } else {
+                    path.clone()
+                };
+
+                error_for_status(
+                    client
+                        .client
+                        .request(Method::GET, Url::parse(&url)?)
+                        .headers(headers.clone())
+                        .send()
+                        .await?,
+                )
+                .await?
+                .bytes()
+                .await?
+                .to_vec()
+            };
+
+        task::block_in_place(|| Tokenizer::from_bytes(&contents)).map_err(anyhow::Error::from_boxed)
+    }
+    async fn local_tokenizer(
+        &self,
+        client: &InferenceClient,
+        cache: &InferenceCache,
+        headers: &HeaderMap,
+        tokenization_identifier: Ulid,
+    ) -> Option<Arc<Tokenizer>> {
+        if let Some(tokenizer) = cache.tokenizers.lock().await.get(&tokenization_identifier) {
+            return Some(tokenizer.clone());
+        }
+
+        // Failed loads aren't cached, so that temporary errors (such as network issues) don't disable local tokenization for the rest of the session
+        match self.load_tokenizer(client, headers).await {
+            Ok(tokenizer) => {
+                let tokenizer = Arc::new(tokenizer);
+
+                cache
+                    .tokenizers
+                    .lock()
+                    .await
+                    .insert(tokenization_identifier, tokenizer.clone());
+
+                Some(tokenizer)
+            }
+            Err(error) => {
+                warn!("Unable to load tokenizer: {error:#?}");
+                None
+            }
+        }
+    }
+    // Returns false if the only configured tokenizer is a local one which can't be loaded, in which case the prompt should be sent as text
+    async fn can_tokenize(
+        &self,
+        client: &InferenceClient,
+        cache: &InferenceCache,
+        headers: &HeaderMap,
+        tokenization_identifier: Ulid,
+    ) -> bool {
+        !self.nonstandard.tokenization_endpoint.is_empty()
+            || (self.nonstandard.has_tokenizer()
+                && self
+                    .local_tokenizer(client, cache, headers, tokenization_identifier)
+                    .await
+                    .is_some())
+    }
+    async fn tokenize(
+        &self,
+        client: &InferenceClient,
+        cache: &InferenceCache,
+        headers: &HeaderMap,
+        tokenization_identifier: Ulid,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<u64>, anyhow::Error> {
+        if self.nonstandard.local_tokenization
+            && let Some(tokenizer) = self
+                .local_tokenizer(client, cache, headers, tokenization_identifier)
+                .await
+        {
+            // Local tokenizers only accept text, so other inputs are tokenized by the tokenization endpoint instead
+            match str::from_utf8(&bytes) {
+                Ok(input) => return task::block_in_place(|| encode_locally(&tokenizer, input)),
+                Err(_) if !self.nonstandard.tokenization_endpoint.is_empty() => {}
+                Err(_) => return Err(invalid_text_error()),
+            }
+        }
+
+        if self.nonstandard.tokenization_endpoint.is_empty() {
+            return Err(anyhow::Error::msg("Unable to load tokenizer"));
+        }
+
+        self.tokenize_remotely(client, headers, bytes).await
+    }
+    async fn tokenize_batch(
+        &self,
+        client: &InferenceClient,
+        cache: &InferenceCache,
+        headers: &HeaderMap,
+        tokenization_identifier: Ulid,
+        inputs: Vec<Vec<u8>>,
+    ) -> Result<Vec<Vec<u64>>, anyhow::Error> {
+        if self.nonstandard.local_tokenization
+            && let Some(tokenizer) = self
+                .local_tokenizer(client, cache, headers, tokenization_identifier)
+                .await
+        {
+            let texts: Option<Vec<&str>> = inputs
+                .iter()
+                .map(|bytes| str::from_utf8(bytes).ok())
+                .collect();
+
+            match texts {
+                Some(texts) => {
+                    return task::block_in_place(|| {
+                        texts
+                            .into_iter()
+                            .map(|input| encode_locally(&tokenizer, input))
+                            .collect()
+                    });
+                }
+                None if !self.nonstandard.tokenization_endpoint.is_empty() => {}
+                None => return Err(invalid_text_error()),
+            }
+        }
+
+        if self.nonstandard.tokenization_endpoint.is_empty() {
+            return Err(anyhow::Error::msg("Unable to load tokenizer"));
+        }
+
+        let body: Vec<Value> = inputs
+            .iter()
+            .map(|bytes| match str::from_utf8(bytes) {
+                Ok(text) => Value::String(text.to_string()),
+                Err(_) => json!({ "base64": BASE64_STANDARD.encode(bytes) }),
+            })
+            .collect();
+
+        let response = client
+            .client
+            .request(
+                Method::POST,
+                Url::parse(
+                    &[
+                        self.nonstandard.tokenization_endpoint.trim_end_matches('/'),
+                        "/tokenize_batch",
+                    ]
+                    .concat(),
+                )?,
+            )
+            .headers(headers.clone())
+            .json(&body)
+            .send()
+            .await?;
+
+        let status = response.status();
+
+        // Tokenization endpoints without batch support are sent one request per input instead
+        if matches!(
+            status,
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            let text = response.text().await?;
+
+            // Tapestry Tokenize also responds with a 404 when the model is unknown, which individual requests would fail with as well
+            if status == StatusCode::NOT_FOUND && text.contains("Unable to find model") {
+                return Err(anyhow::Error::msg(format!(
+                    "HTTP {}: {}",
+                    status.as_u16(),
+                    text
+                )));
+            }
+
+            warn!(
+                "Tokenization endpoint does not support batching, falling back to individual requests"
+            );
+
+            let mut outputs = Vec::with_capacity(inputs.len());
+
+            for bytes in inputs {
+                outputs.push(self.tokenize_remotely(client, headers, bytes).await?);
+            }
+
+            return Ok(outputs);
+        }
+
+        Ok(error_for_status(response).await?.json().await?)
+    }
+    async fn tokenize_remotely(
+        &self,
+        client: &InferenceClient,
+        headers: &HeaderMap,
+        bytes: Vec<u8>,
+    ) -> Result<Vec<u64>, anyhow::Error> {
+        Ok(error_for_status(
+            client
+                .client
+                .request(
+                    Method::POST,
+                    Url::parse(&self.nonstandard.tokenization_endpoint)?,
+                )
+                .headers(headers.clone())
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(bytes)
+                .send()
+                .await?,
+        )
+        .await?
+        .json()
+        .await?)
+    }
+}
+
+fn encode_locally(tokenizer: &Tokenizer, input: &str) -> Result<Vec<u64>, anyhow::Error> {
+    tokenizer
+        .encode_fast(input, false)
+        .map(|encoding| encoding.get_ids().iter().map(|id| *id as u64).collect())
+        .map_err(anyhow::Error::from_boxed)
+}
+
+fn invalid_text_error() -> anyhow::Error {
+    anyhow::Error::msg(
+        "Input is not valid UTF-8, which requires a tokenization endpoint when using a local tokenizer",
+    )
+}
+
 impl Endpoint for OpenAICompletionsConfig {
     fn render_settings(&mut self, ui: &mut Ui, id: &Ulid) -> bool {
         let old = self.clone();
@@ -333,7 +672,7 @@ impl Endpoint for OpenAICompletionsConfig {
 
         ui.group(|ui| {
             ui.label("Request headers:");
-            render_config_map(ui, &mut self.headers, 0.9, 1.1);
+            render_header_map(ui, &mut self.headers);
         });
 
         *self != old
@@ -362,6 +701,44 @@ impl Endpoint for OpenAICompletionsConfig {
             ]
         }
     }
+    fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+    fn parameter_schema(&self) -> &'static [ParameterSpec] {
+        COMPLETIONS_PARAMETERS
+    }
+    async fn count_tokens(
+        &self,
+        client: &InferenceClient,
+        cache: &InferenceCache,
+        content: TokensOrBytes,
+        tokenization_identifier: Ulid,
+    ) -> Result<usize, anyhow::Error> {
+        let headers = self.build_headers()?;
+
+        if !self
+            .can_tokenize(client, cache, &headers, tokenization_identifier)
+            .await
+        {
+            return Ok(estimate_token_count(&content, &tokenization_identifier));
+        }
+
+        Ok(RequestTokensOrBytes::cached_into_tokens_async(
+            vec![RequestTokensOrBytes::build(
+                content,
+                &tokenization_identifier,
+            )],
+            tokenization_identifier,
+            cache,
+            |inputs: Vec<Vec<u8>>| {
+                self.tokenize_batch(client, cache, &headers, tokenization_identifier, inputs)
+            },
+        )
+        .await?
+        .iter()
+        .map(|tokens| tokens.len())
+        .sum())
+    }
     async fn perform_request(
         &self,
         client: &InferenceClient,
@@ -369,14 +746,7 @@ impl Endpoint for OpenAICompletionsConfig {
         request: EndpointRequest,
         tokenization_identifier: Ulid,
     ) -> Result<Vec<EndpointResponse>, anyhow::Error> {
-        let mut headers = HeaderMap::with_capacity(self.headers.len());
-
-        for (key, value) in &self.headers {
-            headers.insert(
-                HeaderName::from_bytes(key.as_bytes())?,
-                HeaderValue::from_str(value)?,
-            );
-        }
+        let headers = self.build_headers()?;
 
         let mut body = Map::with_capacity(1 + request.parameters.len() + self.parameters.len());
 
@@ -400,43 +770,80 @@ impl Endpoint for OpenAICompletionsConfig {
             body.insert("stream".to_string(), Value::Bool(false));
         };
 
-        if self.nonstandard.reuse_tokens && !self.nonstandard.tokenization_endpoint.is_empty() {
-            let mut token_futures = Vec::with_capacity(request.content.len());
-
-            for segment in request.content.as_ref().clone() {
-                token_futures.push(
-                    RequestTokensOrBytes::build(segment, &tokenization_identifier)
-                        .cached_into_tokens_async(
-                            tokenization_identifier,
-                            &cache.tokens,
-                            |bytes: Vec<u8>| async {
-                                Ok(error_for_status(
-                                    client
-                                        .client
-                                        .request(
-                                            Method::POST,
-                                            Url::parse(&self.nonstandard.tokenization_endpoint)?,
-                                        )
-                                        .headers(headers.clone())
-                                        .header(CONTENT_TYPE, "application/octet-stream")
-                                        .body(bytes)
-                                        .send()
-                                        .await?,
-                                )
-                                .await?
-                                .json()
-                                .await?)
-                            },
-                        ),
-                );
-            }
+        if let Some(constraint) = &request.constraint {
+            insert_constraint(&mut body, constraint, self.nonstandard.constraint_style)?;
+        }
 
-            let mut token_segments = Vec::with_capacity(token_futures.len());
+        if !request.phrase_biases.is_empty() {
+            let phrases: Vec<(usize, String, f64)> = request
+                .phrase_biases
+                .iter()
+                .enumerate()
+                .filter(|(_, (phrase, _))| !phrase.is_empty())
+                .flat_map(|(index, (phrase, bias))| {
+                    sampler::phrase_variants(phrase)
+                        .into_iter()
+                        .map(move |variant| (index, variant, *bias))
+                })
+                .collect();
+
+            let tokens = RequestTokensOrBytes::cached_into_tokens_async(
+                phrases
+                    .iter()
+                    .map(|(_, phrase, _)| RequestTokensOrBytes::Bytes(phrase.as_bytes().to_vec()))
+                    .collect(),
+                tokenization_identifier,
+                cache,
+                |inputs: Vec<Vec<u8>>| {
+                    self.tokenize_batch(client, cache, &headers, tokenization_identifier, inputs)
+                },
+            )
+            .await?;
+
+            // Only the first token of each phrase is biased, as later tokens of multi-token phrases are often common on their own
+            // Phrases sharing a first token have their biases summed, but both variants of a single phrase only count once
+            let mut biases: Vec<(u64, f64)> = Vec::with_capacity(phrases.len());
+            let mut counted: HashSet<(usize, u64)> = HashSet::with_capacity(phrases.len());
+
+            for ((index, _, bias), tokens) in phrases.into_iter().zip(tokens) {
+                if let Some(token) = tokens.first()
+                    && counted.insert((index, *token))
+                {
+                    match biases.iter_mut().find(|(existing, _)| existing == token) {
+                        Some((_, existing)) => *existing += bias,
+                        None => biases.push((*token, bias)),
+                    }
+                }
+            }
 
-            for item in token_futures {
-                token_segments.push(item.await?);
+            for (_, bias) in &mut biases {
+                *bias = bias.clamp(-100.0, 100.0);
             }
 
+            insert_logit_bias(&mut body, biases, self.nonstandard.logit_bias_pairs);
+        }
+
+        let can_tokenize = self
+            .can_tokenize(client, cache, &headers, tokenization_identifier)
+            .await;
+
+        if self.nonstandard.reuse_tokens && can_tokenize {
+            let token_segments = RequestTokensOrBytes::cached_into_tokens_async(
+                request
+                    .content
+                    .as_ref()
+                    .clone()
+                    .into_iter()
+                    .map(|segment| RequestTokensOrBytes::build(segment, &tokenization_identifier))
+                    .collect(),
+                tokenization_identifier,
+                cache,
+                |inputs: Vec<Vec<u8>>| {
+                    self.tokenize_batch(client, cache, &headers, tokenization_identifier, inputs)
+                },
+            )
+            .await?;
+
             body.insert(
                 "prompt".to_string(),
                 Value::Array(
@@ -456,25 +863,26 @@ impl Endpoint for OpenAICompletionsConfig {
                 .flat_map(|t| t.into_bytes())
                 .collect();
 
-            if !self.nonstandard.tokenization_endpoint.is_empty() {
-                let tokenized: Value = error_for_status(
-                    client
-                        .client
-                        .request(
-                            Method::POST,
-                            Url::parse(&self.nonstandard.tokenization_endpoint)?,
-                        )
-                        .headers(headers.clone())
-                        .header(CONTENT_TYPE, "application/octet-stream")
-                        .body(request_bytes)
-                        .send()
-                        .await?,
-                )
-                .await?
-                .json()
-                .await?;
+            if can_tokenize {
+                let tokenized = self
+                    .tokenize(
+                        client,
+                        cache,
+                        &headers,
+                        tokenization_identifier,
+                        request_bytes,
+                    )
+                    .await?;
 
-                body.insert("prompt".to_string(), tokenized);
+                body.insert(
+                    "prompt".to_string(),
+                    Value::Array(
+                        tokenized
+                            .into_iter()
+                            .map(|t| Value::Number(Number::from_u128(t.into()).unwrap()))
+                            .collect(),
+                    ),
+                );
             } else {
                 body.insert(
                     "prompt".to_string(),
@@ -508,38 +916,112 @@ impl Endpoint for OpenAICompletionsConfig {
             *prompt = Value::String(String::new());
         }
 
-        let response: Map<String, Value> = error_for_status(
-            client
-                .client
-                .request(Method::POST, Url::parse(&self.endpoint)?)
-                .headers(headers)
-                .json(&Value::Object(body))
-                .send()
-                .await?,
+        send_request(
+            client,
+            &self.endpoint,
+            &self.headers,
+            body,
+            ResponseOptions {
+                metadata: request.parameters.as_ref().clone(),
+                tokenization_identifier,
+                echo,
+                single_token,
+                requested_top,
+                model: request.model,
+            },
+            request.capture,
         )
-        .await?
-        .json()
-        .await?;
+        .await
+    }
+    async fn probe(&self, client: &InferenceClient) -> Result<ProbeReport, anyhow::Error> {
+        let headers = self.build_headers()?;
 
-        let metadata = request.parameters.as_ref().clone();
+        let build_body = |fields: Vec<(&str, Value)>| {
+            let mut body = Map::with_capacity(self.parameters.len() + fields.len() + 2);
 
-        let endpoint_response = parse_response(
-            response,
-            metadata,
-            tokenization_identifier,
-            echo,
-            single_token,
-            requested_top,
-        );
+            build_json_object(&mut body, self.parameters.clone());
+            body.remove("stream");
+            body.insert(
+                "prompt".to_string(),
+                Value::String(PROBE_PROMPT.to_string()),
+            );
+            body.insert("max_tokens".to_string(), Value::from(1));
 
-        if !endpoint_response.is_empty() {
-            Ok(endpoint_response)
-        } else {
-            Err(response_schema_error())
+            for (key, value) in fields {
+                body.insert(key.to_string(), value);
+            }
+
+            body
+        };
+
+        post_json(client, &self.endpoint, &headers, build_body(Vec::new())).await?;
+
+        let logprobs = post_json(
+            client,
+            &self.endpoint,
+            &headers,
+            build_body(vec![("logprobs", Value::from(1))]),
+        )
+        .await
+        .is_ok_and(|response| {
+            response
+                .pointer("/choices/0/logprobs")
+                .is_some_and(|logprobs| !logprobs.is_null())
+        });
+
+        let echo = post_json(
+            client,
+            &self.endpoint,
+            &headers,
+            build_body(vec![("echo", Value::Bool(true))]),
+        )
+        .await
+        .is_ok_and(|response| {
+            response
+                .pointer("/choices/0/text")
+                .and_then(Value::as_str)
+                .is_some_and(|text| text.starts_with(PROBE_PROMPT))
+        });
+
+        let suffix = post_json(
+            client,
+            &self.endpoint,
+            &headers,
+            build_body(vec![("suffix", Value::String(PROBE_PROMPT.to_string()))]),
+        )
+        .await
+        .is_ok();
+
+        let token_prompts = post_json(
+            client,
+            &self.endpoint,
+            &headers,
+            build_body(vec![("prompt", Value::Array(vec![Value::from(1)]))]),
+        )
+        .await
+        .is_ok_and(|response| response.pointer("/choices/0").is_some());
+
+        Ok(ProbeReport {
+            server: detect_server(client, &self.endpoint, &headers, self.label()).await,
+            logprobs: Some(logprobs),
+            echo: Some(echo),
+            suffix: Some(suffix),
+            token_prompts: Some(token_prompts),
+        })
+    }
+    fn apply_probe(&mut self, report: &ProbeReport) {
+        if report.token_prompts == Some(false) {
+            self.nonstandard.reuse_tokens = false;
+        }
+
+        if let Some(style) = report.constraint_style() {
+            self.nonstandard.constraint_style = style;
         }
     }
 }
 
+const PROBE_PROMPT: &str = "Hello";
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub(super) struct OpenAIChatCompletionsConfig {
     pub(super) endpoint: String,
@@ -553,6 +1035,18 @@ pub(super) struct OpenAIChatCompletionsConfig {
     #[serde(default)]
     pub(super) suffix_messages: Vec<String>,
 
+    #[serde(default)]
+    pub(super) map_turns: bool,
+
+    #[serde(default = "default_human_role")]
+    pub(super) human_role: String,
+
+    #[serde(default = "default_context_role")]
+    pub(super) context_role: String,
+
+    #[serde(default)]
+    pub(super) system_prompt: String,
+
     pub(super) parameters: Vec<(String, String)>,
     pub(super) headers: Vec<(String, String)>,
 
@@ -564,6 +1058,126 @@ fn default_message_role() -> String {
     "assistant".to_string()
 }
 
+fn default_human_role() -> String {
+    "user".to_string()
+}
+
+fn default_context_role() -> String {
+    "system".to_string()
+}
+
+impl OpenAIChatCompletionsConfig {
+    fn build_message(&self, role: &str, content: String) -> Value {
+        let mut message = Map::with_capacity(self.nonstandard.chat_message_custom_fields.len() + 2);
+
+        build_json_object(
+            &mut message,
+            self.nonstandard.chat_message_custom_fields.clone(),
+        );
+
+        message.insert("role".to_string(), Value::String(role.to_string()));
+        message.insert("content".to_string(), Value::String(content));
+
+        Value::Object(message)
+    }
+    fn build_turn(&self, source: SegmentSource, bytes: &[u8]) -> Value {
+        let role = match source {
+            SegmentSource::Human => &self.human_role,
+            SegmentSource::Model => &self.message_role,
+            SegmentSource::Context => &self.context_role,
+        };
+
+        self.build_message(role, String::from_utf8_lossy(bytes).to_string())
+    }
+    fn completions_endpoint(&self) -> Cow<'_, str> {
+        if !self.nonstandard.completions_endpoint.is_empty() {
+            Cow::Borrowed(&self.nonstandard.completions_endpoint)
+        } else if let Some(base) = self.endpoint.strip_suffix("/chat/completions") {
+            Cow::Owned([base, "/completions"].concat())
+        } else {
+            Cow::Borrowed(&self.endpoint)
+        }
+    }
+    async fn apply_chat_template(
+        &self,
+        client: &InferenceClient,
+        messages: Vec<Value>,
+    ) -> Result<String, anyhow::Error> {
+        if self.nonstandard.tokenization_endpoint.is_empty() {
+            return Err(anyhow::Error::msg(
+                "A Tapestry-Tokenize endpoint is required to apply the chat template",
+            ));
+        }
+
+        // A trailing message using the model role is continued rather than followed by a new turn
+        let continue_final_message = messages.last().is_some_and(|message| {
+            message.get("role").and_then(Value::as_str) == Some(self.message_role.as_str())
+                && message
+                    .get("content")
+                    .and_then(Value::as_str)
+                    .is_some_and(|content| !content.trim().is_empty())
+        });
+
+        let mut body = Map::with_capacity(4);
+        body.insert("messages".to_string(), Value::Array(messages));
+        body.insert(
+            "add_generation_prompt".to_string(),
+            Value::Bool(!continue_final_message),
+        );
+        body.insert(
+            "continue_final_message".to_string(),
+            Value::Bool(continue_final_message),
+        );
+        // The Completions endpoint adds its own BOS token to the prompt
+        body.insert("strip_bos_token".to_string(), Value::Bool(true));
+
+        Ok(error_for_status(
+            client
+                .client
+                .request(
+                    Method::POST,
+                    Url::parse(
+                        &[
+                            self.nonstandard.tokenization_endpoint.trim_end_matches('/'),
+                            "/apply_chat_template",
+                        ]
+                        .concat(),
+                    )?,
+                )
+                .headers(build_headers(&self.headers)?)
+                .json(&body)
+                .send()
+                .await?,
+        )
+        .await?
+        .text()
+        .await?)
+    }
+}
+
+// Converts Chat Completions logprob parameters into their Completions equivalents
+fn convert_chat_parameters(body: &mut Map<String, Value>) {
+    match body.remove("logprobs") {
+        Some(Value::Bool(true)) => {
+            let top = body.remove("top_logprobs").unwrap_or(Value::from(1));
+            body.insert("logprobs".to_string(), top);
+        }
+        Some(Value::Bool(false)) => {
+            body.remove("top_logprobs");
+        }
+        Some(logprobs) => {
+            body.insert("logprobs".to_string(), logprobs);
+        }
+        None => {}
+    }
+
+    if !body.contains_key("max_tokens")
+        && let Some(max_tokens) = body.remove("max_completion_tokens")
+    {
+        body.insert("max_tokens".to_string(), max_tokens);
+    }
+}
+
 impl Endpoint for OpenAIChatCompletionsConfig {
     fn render_settings(&mut self, ui: &mut Ui, id: &Ulid) -> bool {
         let old = self.clone();
@@ -598,14 +1212,50 @@ impl Endpoint for OpenAIChatCompletionsConfig {
         });
 
         ui.group(|ui| {
+            ui.checkbox(&mut self.map_turns, "Map thread to conversation turns")
+                .on_hover_text("Sends the active thread as a multi-turn conversation rather than a single message. Consecutive nodes written by a human are sent as one turn using the human role, consecutive nodes generated by a model are sent as one turn using the model role, and memory and author's notes are sent using the context role.");
+
             ui.horizontal_wrapped(|ui| {
-                let label = ui.label("Message role:").id;
+                let label = ui
+                    .label(if self.map_turns {
+                        "Model role:"
+                    } else {
+                        "Message role:"
+                    })
+                    .id;
                 TextEdit::singleline(&mut self.message_role)
                     .hint_text("assistant")
                     .clip_text(false)
                     .ui(ui)
                     .labelled_by(label);
             });
+
+            if self.map_turns {
+                ui.horizontal_wrapped(|ui| {
+                    let label = ui.label("Human role:").id;
+                    TextEdit::singleline(&mut self.human_role)
+                        .hint_text("user")
+                        .clip_text(false)
+                        .ui(ui)
+                        .labelled_by(label);
+                });
+
+                ui.horizontal_wrapped(|ui| {
+                    let label = ui.label("Context role:").id;
+                    TextEdit::singleline(&mut self.context_role)
+                        .hint_text("system")
+                        .clip_text(false)
+                        .ui(ui)
+                        .labelled_by(label);
+                });
+
+                let label = ui.label("System prompt:").id;
+                TextEdit::multiline(&mut self.system_prompt)
+                    .hint_text("Optional; sent before the prefix messages")
+                    .desired_rows(2)
+                    .ui(ui)
+                    .labelled_by(label);
+            }
         });
 
         if !self.suffix_messages.is_empty() {
@@ -623,7 +1273,7 @@ impl Endpoint for OpenAIChatCompletionsConfig {
 
         ui.group(|ui| {
             ui.label("Request headers:");
-            render_config_map(ui, &mut self.headers, 0.9, 1.1);
+            render_header_map(ui, &mut self.headers);
         });
 
         *self != old
@@ -653,6 +1303,21 @@ impl Endpoint for OpenAIChatCompletionsConfig {
             ]
         }
     }
+    fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+    fn parameter_schema(&self) -> &'static [ParameterSpec] {
+        CHAT_COMPLETIONS_PARAMETERS
+    }
+    async fn count_tokens(
+        &self,
+        _client: &InferenceClient,
+        _cache: &InferenceCache,
+        content: TokensOrBytes,
+        tokenization_identifier: Ulid,
+    ) -> Result<usize, anyhow::Error> {
+        Ok(estimate_token_count(&content, &tokenization_identifier))
+    }
     async fn perform_request(
         &self,
         client: &InferenceClient,
@@ -664,13 +1329,10 @@ impl Endpoint for OpenAIChatCompletionsConfig {
             return Err(anyhow::Error::msg("Endpoint does not support FIM"));
         }
 
-        let mut headers = HeaderMap::with_capacity(self.headers.len());
-
-        for (key, value) in &self.headers {
-            headers.insert(
-                HeaderName::from_bytes(key.as_bytes())?,
-                HeaderValue::from_str(value)?,
-            );
+        if !request.phrase_biases.is_empty() {
+            return Err(anyhow::Error::msg(
+                "Endpoint does not support phrase biases",
+            ));
         }
 
         let mut body = Map::with_capacity(1 + request.parameters.len() + self.parameters.len());
@@ -678,6 +1340,10 @@ impl Endpoint for OpenAIChatCompletionsConfig {
         build_json_object(&mut body, self.parameters.clone());
         build_json_object(&mut body, request.parameters.as_ref().clone());
 
+        if let Some(constraint) = &request.constraint {
+            insert_constraint(&mut body, constraint, self.nonstandard.constraint_style)?;
+        }
+
         let single_token = body
             .get("max_tokens")
             .and_then(|t| t.as_u64())
@@ -693,76 +1359,156 @@ impl Endpoint for OpenAIChatCompletionsConfig {
             body.insert("stream".to_string(), Value::Bool(false));
         };
 
-        let mut message = Map::with_capacity(self.nonstandard.chat_message_custom_fields.len() + 2);
+        let mut messages =
+            Vec::with_capacity(self.prefix_messages.len() + self.suffix_messages.len() + 2);
 
-        build_json_object(
-            &mut message,
-            self.nonstandard.chat_message_custom_fields.clone(),
-        );
+        if self.map_turns && !self.system_prompt.is_empty() {
+            messages.push(self.build_message(&self.context_role, self.system_prompt.clone()));
+        }
 
-        message.insert("role".to_string(), Value::String(self.message_role.clone()));
+        build_json_list(&mut messages, self.prefix_messages.clone());
 
-        let request_bytes: Vec<u8> = request
-            .content
-            .as_ref()
-            .clone()
-            .into_iter()
-            .flat_map(|t| t.into_bytes())
-            .collect();
+        if self.map_turns {
+            let mut turn: Option<(SegmentSource, Vec<u8>)> = None;
 
-        message.insert(
-            "content".to_string(),
-            Value::String(String::from_utf8_lossy(&request_bytes).to_string()),
-        );
+            for (index, segment) in request.content.as_ref().clone().into_iter().enumerate() {
+                let source = request
+                    .sources
+                    .get(index)
+                    .copied()
+                    .unwrap_or(SegmentSource::Human);
+                let bytes = segment.into_bytes();
 
-        let mut messages =
-            Vec::with_capacity(self.prefix_messages.len() + self.suffix_messages.len() + 1);
+                if bytes.is_empty() {
+                    continue;
+                }
 
-        build_json_list(&mut messages, self.prefix_messages.clone());
+                match &mut turn {
+                    Some((turn_source, turn_bytes)) if *turn_source == source => {
+                        turn_bytes.extend(bytes);
+                    }
+                    _ => {
+                        if let Some((source, bytes)) = turn.replace((source, bytes)) {
+                            messages.push(self.build_turn(source, &bytes));
+                        }
+                    }
+                }
+            }
 
-        /*if !(request_bytes.is_empty()
-            && !self.prefix_messages.is_empty()
-            && self.suffix_messages.is_empty())
-        {
-            messages.push(Value::Object(message));
-        }*/
+            if let Some((source, bytes)) = turn {
+                messages.push(self.build_turn(source, &bytes));
+            }
+        } else {
+            let request_bytes: Vec<u8> = request
+                .content
+                .as_ref()
+                .clone()
+                .into_iter()
+                .flat_map(|t| t.into_bytes())
+                .collect();
 
-        messages.push(Value::Object(message));
+            /*if !(request_bytes.is_empty()
+                && !self.prefix_messages.is_empty()
+                && self.suffix_messages.is_empty())
+            {
+                messages.push(Value::Object(message));
+            }*/
+
+            messages.push(self.build_message(
+                &self.message_role,
+                String::from_utf8_lossy(&request_bytes).to_string(),
+            ));
+        }
 
         build_json_list(&mut messages, self.suffix_messages.clone());
 
-        body.insert("messages".to_string(), Value::Array(messages));
+        let (endpoint, requested_top) = if self.nonstandard.chat_template {
+            let prompt = self.apply_chat_template(client, messages).await?;
+
+            convert_chat_parameters(&mut body);
+            body.insert("prompt".to_string(), Value::String(prompt));
+
+            let requested_top = body
+                .get("logprobs")
+                .and_then(|t| t.as_u64())
+                .map(|t| t as usize);
+
+            (self.completions_endpoint(), requested_top)
+        } else {
+            body.insert("messages".to_string(), Value::Array(messages));
+
+            (Cow::Borrowed(self.endpoint.as_str()), requested_top)
+        };
 
         trace!("{:#?}", &body);
 
-        let response: Map<String, Value> = error_for_status(
-            client
-                .client
-                .request(Method::POST, Url::parse(&self.endpoint)?)
-                .headers(headers)
-                .json(&Value::Object(body))
-                .send()
-                .await?,
+        send_request(
+            client,
+            &endpoint,
+            &self.headers,
+            body,
+            ResponseOptions {
+                metadata: request.parameters.as_ref().clone(),
+                tokenization_identifier,
+                echo: false,
+                single_token,
+                requested_top,
+                model: request.model,
+            },
+            request.capture,
         )
-        .await?
-        .json()
-        .await?;
+        .await
+    }
+    async fn probe(&self, client: &InferenceClient) -> Result<ProbeReport, anyhow::Error> {
+        let headers = build_headers(&self.headers)?;
 
-        let metadata = request.parameters.as_ref().clone();
+        let build_body = |fields: Vec<(&str, Value)>| {
+            let mut body = Map::with_capacity(self.parameters.len() + fields.len() + 2);
 
-        let endpoint_response = parse_response(
-            response,
-            metadata,
-            tokenization_identifier,
-            false,
-            single_token,
-            requested_top,
-        );
+            build_json_object(&mut body, self.parameters.clone());
+            body.remove("stream");
+            body.insert(
+                "messages".to_string(),
+                Value::Array(vec![
+                    self.build_message(&self.human_role, PROBE_PROMPT.to_string()),
+                ]),
+            );
+            body.insert("max_tokens".to_string(), Value::from(1));
 
-        if !endpoint_response.is_empty() {
-            Ok(endpoint_response)
-        } else {
-            Err(response_schema_error())
+            for (key, value) in fields {
+                body.insert(key.to_string(), value);
+            }
+
+            body
+        };
+
+        post_json(client, &self.endpoint, &headers, build_body(Vec::new())).await?;
+
+        let logprobs = post_json(
+            client,
+            &self.endpoint,
+            &headers,
+            build_body(vec![
+                ("logprobs", Value::Bool(true)),
+                ("top_logprobs", Value::from(1)),
+            ]),
+        )
+        .await
+        .is_ok_and(|response| {
+            response
+                .pointer("/choices/0/logprobs")
+                .is_some_and(|logprobs| !logprobs.is_null())
+        });
+
+        Ok(ProbeReport {
+            server: detect_server(client, &self.endpoint, &headers, self.label()).await,
+            logprobs: Some(logprobs),
+            ..Default::default()
+        })
+    }
+    fn apply_probe(&mut self, report: &ProbeReport) {
+        if let Some(style) = report.constraint_style() {
+            self.nonstandard.constraint_style = style;
         }
     }
 }
@@ -777,6 +1523,24 @@ pub(super) struct NonStandardOpenAIModifications {
 
     #[serde(default)]
     pub(super) chat_message_custom_fields: Vec<(String, String)>,
+
+    #[serde(default)]
+    pub(super) local_tokenization: bool,
+
+    #[serde(default)]
+    pub(super) tokenizer_path: String,
+
+    #[serde(default)]
+    pub(super) logit_bias_pairs: bool,
+
+    #[serde(default)]
+    pub(super) constraint_style: ConstraintStyle,
+
+    #[serde(default)]
+    pub(super) chat_template: bool,
+
+    #[serde(default)]
+    pub(super) completions_endpoint: String,
 }
 
 impl Default for NonStandardOpenAIModifications {
@@ -785,6 +1549,12 @@ impl Default for NonStandardOpenAIModifications {
             tokenization_endpoint: String::new(),
             reuse_tokens: true,
             chat_message_custom_fields: Vec::new(),
+            local_tokenization: false,
+            tokenizer_path: String::new(),
+            logit_bias_pairs: false,
+            constraint_style: ConstraintStyle::default(),
+            chat_template: false,
+            completions_endpoint: String::new(),
         }
     }
 }
@@ -800,6 +1570,23 @@ impl NonStandardOpenAIModifications {
                 ui.label("Additional input message parameters:");
                 render_config_map(ui, &mut self.chat_message_custom_fields, 0.675, 0.825);
             });
+
+            ui.checkbox(&mut self.chat_template, "Send as raw completion prompt")
+                .on_hover_text("Renders the messages using the model's chat template (provided by the Tapestry-Tokenize endpoint) and sends the result to a Completions endpoint as a raw prompt.\n\nThis allows logprobs to be used with servers whose Chat Completions endpoints do not return them. Chat-specific logprob parameters are converted into their Completions equivalents.");
+
+            if self.chat_template {
+                TextEdit::singleline(&mut self.tokenization_endpoint)
+                    .hint_text("Tapestry-Tokenize Endpoint")
+                    .desired_width(ui.spacing().text_edit_width * 1.5)
+                    .ui(ui)
+                    .on_hover_text("Tapestry-Tokenize Endpoint\n\nThe model must have a tokenizer_config file specified in Tapestry-Tokenize's models.toml.");
+
+                TextEdit::singleline(&mut self.completions_endpoint)
+                    .hint_text("Completions endpoint URL (optional)")
+                    .desired_width(ui.spacing().text_edit_width * 1.5)
+                    .ui(ui)
+                    .on_hover_text("The endpoint used for sending the rendered prompt. If left empty, the endpoint URL is used with /chat/completions replaced by /completions.");
+            }
         } else {
             TextEdit::singleline(&mut self.tokenization_endpoint)
                 .hint_text("Tapestry-Tokenize Endpoint")
@@ -807,19 +1594,49 @@ impl NonStandardOpenAIModifications {
                 .ui(ui)
                 .on_hover_text("Tapestry-Tokenize Endpoint");
 
-            if !self.tokenization_endpoint.is_empty() {
+            ui.checkbox(&mut self.local_tokenization, "Tokenize locally")
+                .on_hover_text("Loads the model's tokenizer.json file and tokenizes inputs without making HTTP requests. The Tapestry-Tokenize endpoint is used as a fallback if the tokenizer cannot be loaded.");
+
+            if self.local_tokenization {
+                TextEdit::singleline(&mut self.tokenizer_path)
+                    .hint_text(if self.tokenization_endpoint.is_empty() {
+                        "tokenizer.json path or URL"
+                    } else {
+                        "Download from Tapestry-Tokenize endpoint"
+                    })
+                    .desired_width(ui.spacing().text_edit_width * 1.5)
+                    .ui(ui)
+                    .on_hover_text("tokenizer.json path or URL");
+            }
+
+            if self.has_tokenizer() {
                 ui.checkbox(
                     &mut self.reuse_tokens,
                     "(Opportunistically) reuse output token IDs",
                 ).on_hover_text("Reuses token IDs from the model's output whenever possible rather than retokenizing the input.\n\nThis may improve output quality, especially when nodes are being generated by one model rather than an ensemble of models.");
+
+                ui.checkbox(
+                    &mut self.logit_bias_pairs,
+                    "Send logit biases as [token, bias] pairs",
+                ).on_hover_text("Sends the logit_bias parameter as a list of [token ID, bias] pairs (as used by llama.cpp's native API) rather than an OpenAI-style object mapping token IDs to biases.");
             }
         }
+
+        self.constraint_style.render(ui);
+    }
+    fn has_tokenizer(&self) -> bool {
+        !self.tokenization_endpoint.is_empty()
+            || (self.local_tokenization && !self.tokenizer_path.is_empty())
     }
     #[allow(clippy::nonminimal_bool)]
     fn is_standard(&self) -> bool {
-        !(self.reuse_tokens && !self.tokenization_endpoint.is_empty())
+        !(self.reuse_tokens && self.has_tokenizer())
             && self.tokenization_endpoint.is_empty()
+            && !self.local_tokenization
+            && !self.logit_bias_pairs
+            && self.constraint_style == ConstraintStyle::default()
             && self.chat_message_custom_fields.is_empty()
+            && !self.chat_template
     }
 }
 
@@ -833,6 +1650,23 @@ pub(super) struct OpenAIEmbeddingsConfig {
     pub(super) prefix: String,
 }
 
+impl OpenAIEmbeddingsConfig {
+    // Headers are excluded, as they usually contain credentials rather than anything affecting the output
+    pub(super) fn cache_namespace(&self) -> u64 {
+        let mut parts: Vec<&[u8]> = Vec::with_capacity(2 + self.parameters.len() * 2);
+
+        parts.push(self.endpoint.as_bytes());
+        parts.push(self.prefix.as_bytes());
+
+        for (key, value) in &self.parameters {
+            parts.push(key.as_bytes());
+            parts.push(value.as_bytes());
+        }
+
+        hash_key(&parts)
+    }
+}
+
 impl Default for OpenAIEmbeddingsConfig {
     fn default() -> Self {
         Self {
@@ -877,7 +1711,7 @@ impl EmbeddingEndpoint for OpenAIEmbeddingsConfig {
 
         ui.group(|ui| {
             ui.label("Request headers:");
-            render_config_map(ui, &mut self.headers, 0.9, 1.1);
+            render_header_map(ui, &mut self.headers);
         });
 
         ui.group(|ui| {
@@ -897,19 +1731,31 @@ impl EmbeddingEndpoint for OpenAIEmbeddingsConfig {
         cache: &InferenceCache,
         request: Vec<u8>,
     ) -> Result<Vec<f32>, anyhow::Error> {
-        if let Some(embedding) = cache.embeddings.lock().await.get(&request) {
+        let namespace = self.cache_namespace();
+
+        if let Some(embedding) = cache
+            .embeddings
+            .lock()
+            .await
+            .get(&(namespace, request.clone()))
+        {
             return Ok(embedding.clone());
         };
 
-        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        if let Some(embedding) = cache
+            .persistent_embeddings
+            .get::<Vec<f32>>(namespace, &request)
+            .await
+        {
+            cache
+                .insert_embedding(namespace, request, embedding.clone())
+                .await;
 
-        for (key, value) in &self.headers {
-            headers.insert(
-                HeaderName::from_bytes(key.as_bytes())?,
-                HeaderValue::from_str(value)?,
-            );
+            return Ok(embedding);
         }
 
+        let headers = build_headers(&self.headers)?;
+
         let mut body = Map::with_capacity(1 + self.parameters.len());
 
         build_json_object(&mut body, self.parameters.clone());
@@ -942,13 +1788,13 @@ impl EmbeddingEndpoint for OpenAIEmbeddingsConfig {
 
         match parse_embedding_response(response) {
             Some(embedding) => {
-                let mut embeddings_cache = cache.embeddings.lock().await;
-
-                embeddings_cache.insert(request, embedding.clone());
-
-                while embeddings_cache.len() >= EMBEDDING_CACHE_MAX_SIZE - 1 {
-                    embeddings_cache.pop_front();
-                }
+                cache
+                    .persistent_embeddings
+                    .insert(namespace, request.clone(), embedding.clone())
+                    .await;
+                cache
+                    .insert_embedding(namespace, request, embedding.clone())
+                    .await;
 
                 Ok(embedding)
             }
@@ -956,3 +1802,60 @@ impl EmbeddingEndpoint for OpenAIEmbeddingsConfig {
         }
     }
 }
+
+fn insert_logit_bias(body: &mut Map<String, Value>, biases: Vec<(u64, f64)>, pairs: bool) {
+    let existing = body.remove("logit_bias");
+
+    if pairs {
+        let mut list = match existing {
+            Some(Value::Array(list)) => list,
+            Some(Value::Object(map)) => map
+                .into_iter()
+                .map(|(token, bias)| {
+                    Value::Array(vec![
+                        token
+                            .parse::<u64>()
+                            .map(Value::from)
+                            .unwrap_or(Value::String(token)),
+                        bias,
+                    ])
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        list.extend(
+            biases
+                .into_iter()
+                .map(|(token, bias)| Value::Array(vec![Value::from(token), Value::from(bias)])),
+        );
+
+        body.insert("logit_bias".to_string(), Value::Array(list));
+    } else {
+        let mut map = match existing {
+            Some(Value::Object(map)) => map,
+            Some(Value::Array(list)) => list
+                .into_iter()
+                .filter_map(|pair| match pair {
+                    Value::Array(mut pair) if pair.len() == 2 => {
+                        let bias = pair.pop()?;
+                        let token = match pair.pop()? {
+                            Value::String(token) => token,
+                            token => token.to_string(),
+                        };
+
+                        Some((token, bias))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => Map::new(),
+        };
+
+        for (token, bias) in biases {
+            map.insert(token.to_string(), Value::from(bias));
+        }
+
+        body.insert("logit_bias".to_string(), Value::Object(map));
+    }
+}
@@ -0,0 +1,410 @@
+use eframe::egui::{
+    CollapsingHeader, ComboBox, DragValue, RichText, Slider, SliderClamping, TextEdit, Ui, Widget,
+};
+use tapestry_weave::universal_weave::indexmap::IndexMap;
+
+use super::{render_config_list, semantic::edit_similarity};
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ParameterKind {
+    Float(f64, f64),
+    Integer(i64, i64),
+    Boolean,
+    StringList,
+    TokenBias,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ParameterSpec {
+    pub key: &'static str,
+    pub kind: ParameterKind,
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+const TEMPERATURE: ParameterSpec = ParameterSpec {
+    key: "temperature",
+    kind: ParameterKind::Float(0.0, 2.0),
+    default: "1",
+    description: "Scales the logits before sampling. Lower values make outputs more deterministic, higher values make them more varied.",
+};
+const TOP_P: ParameterSpec = ParameterSpec {
+    key: "top_p",
+    kind: ParameterKind::Float(0.0, 1.0),
+    default: "1",
+    description: "Samples only from the smallest set of tokens whose cumulative probability exceeds this value.",
+};
+const TOP_K: ParameterSpec = ParameterSpec {
+    key: "top_k",
+    kind: ParameterKind::Integer(-1, 1000),
+    default: "40",
+    description: "Samples only from the k most likely tokens. Not supported by all servers; some use -1 or 0 to disable it.",
+};
+const MIN_P: ParameterSpec = ParameterSpec {
+    key: "min_p",
+    kind: ParameterKind::Float(0.0, 1.0),
+    default: "0.05",
+    description: "Discards tokens whose probability is below this fraction of the most likely token's probability. Not supported by all servers.",
+};
+const REPETITION_PENALTY: ParameterSpec = ParameterSpec {
+    key: "repetition_penalty",
+    kind: ParameterKind::Float(0.0, 2.0),
+    default: "1",
+    description: "Multiplicative penalty applied to tokens which already appear in the context; 1 disables it. Not supported by all servers.",
+};
+const FREQUENCY_PENALTY: ParameterSpec = ParameterSpec {
+    key: "frequency_penalty",
+    kind: ParameterKind::Float(-2.0, 2.0),
+    default: "0",
+    description: "Additive penalty scaled by how often a token already appears in the output.",
+};
+const PRESENCE_PENALTY: ParameterSpec = ParameterSpec {
+    key: "presence_penalty",
+    kind: ParameterKind::Float(-2.0, 2.0),
+    default: "0",
+    description: "Additive penalty applied to every token which already appears in the output.",
+};
+const MAX_TOKENS: ParameterSpec = ParameterSpec {
+    key: "max_tokens",
+    kind: ParameterKind::Integer(1, i64::MAX),
+    default: "10",
+    description: "The maximum number of tokens to generate.",
+};
+const STOP: ParameterSpec = ParameterSpec {
+    key: "stop",
+    kind: ParameterKind::StringList,
+    default: "[]",
+    description: "Sequences which end generation when produced. Many servers limit the number of stop sequences.",
+};
+const SEED: ParameterSpec = ParameterSpec {
+    key: "seed",
+    kind: ParameterKind::Integer(0, i64::MAX),
+    default: "0",
+    description: "Seed used for sampling. Repeated requests with the same seed and parameters should return the same output, although not all servers guarantee this.",
+};
+const LOGIT_BIAS: ParameterSpec = ParameterSpec {
+    key: "logit_bias",
+    kind: ParameterKind::TokenBias,
+    default: "{}",
+    description: "Bias added to the logits of specific token IDs before sampling, from -100 (ban) to 100 (force).",
+};
+
+pub(super) const COMPLETIONS_PARAMETERS: &[ParameterSpec] = &[
+    TEMPERATURE,
+    TOP_P,
+    TOP_K,
+    MIN_P,
+    REPETITION_PENALTY,
+    FREQUENCY_PENALTY,
+    PRESENCE_PENALTY,
+    MAX_TOKENS,
+    ParameterSpec {
+        key: "logprobs",
+        kind: ParameterKind::Integer(0, 20),
+        default: "20",
+        description: "The number of most likely alternatives returned for each token.",
+    },
+    STOP,
+    SEED,
+    LOGIT_BIAS,
+];
+
+pub(super) const CHAT_COMPLETIONS_PARAMETERS: &[ParameterSpec] = &[
+    TEMPERATURE,
+    TOP_P,
+    TOP_K,
+    MIN_P,
+    REPETITION_PENALTY,
+    FREQUENCY_PENALTY,
+    PRESENCE_PENALTY,
+    MAX_TOKENS,
+    ParameterSpec {
+        key: "logprobs",
+        kind: ParameterKind::Boolean,
+        default: "true",
+        description: "Whether token probabilities are returned.",
+    },
+    ParameterSpec {
+        key: "top_logprobs",
+        kind: ParameterKind::Integer(0, 20),
+        default: "20",
+        description: "The number of most likely alternatives returned for each token. Requires logprobs to be enabled.",
+    },
+    STOP,
+    SEED,
+    LOGIT_BIAS,
+];
+
+impl ParameterKind {
+    fn is_valid(&self, value: &str) -> bool {
+        match self {
+            Self::Float(_, _) => value.trim().parse::<f64>().is_ok(),
+            Self::Integer(_, _) => value.trim().parse::<i64>().is_ok(),
+            Self::Boolean => value.trim().parse::<bool>().is_ok(),
+            Self::StringList => true,
+            Self::TokenBias => serde_json::from_str::<IndexMap<String, f64>>(value).is_ok(),
+        }
+    }
+    fn render(&self, ui: &mut Ui, value: &mut String) {
+        match self {
+            Self::Float(min, max) => {
+                let mut number = value.trim().parse::<f64>().unwrap_or_default();
+                if Slider::new(&mut number, *min..=*max)
+                    .clamping(SliderClamping::Never)
+                    .step_by(0.01)
+                    .max_decimals(2)
+                    .ui(ui)
+                    .changed()
+                {
+                    *value = number.to_string();
+                }
+            }
+            Self::Integer(min, max) => {
+                let mut number = value.trim().parse::<i64>().unwrap_or_default();
+                if DragValue::new(&mut number)
+                    .range(*min..=*max)
+                    .ui(ui)
+                    .changed()
+                {
+                    *value = number.to_string();
+                }
+            }
+            Self::Boolean => {
+                let mut enabled = value.trim().parse::<bool>().unwrap_or_default();
+                if ui.checkbox(&mut enabled, "").changed() {
+                    *value = enabled.to_string();
+                }
+            }
+            Self::StringList => {
+                // Values which aren't JSON arrays are sent as a single stop sequence
+                let mut items = serde_json::from_str::<Vec<String>>(value).unwrap_or_else(|_| {
+                    if value.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![
+                            serde_json::from_str::<String>(value).unwrap_or_else(|_| value.clone()),
+                        ]
+                    }
+                });
+                let original = items.clone();
+
+                ui.vertical(|ui| {
+                    render_config_list(ui, &mut items, Some("sequence"), None, 0.6);
+                });
+
+                if items != original {
+                    *value = serde_json::to_string(&items).unwrap_or_default();
+                }
+            }
+            Self::TokenBias => {
+                let entries: Vec<(String, f64)> =
+                    serde_json::from_str::<IndexMap<String, f64>>(value)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .collect();
+                let mut edited = entries.clone();
+                let mut remove = None;
+
+                ui.vertical(|ui| {
+                    for (index, (token, bias)) in edited.iter_mut().enumerate() {
+                        ui.horizontal_wrapped(|ui| {
+                            TextEdit::singleline(token)
+                                .hint_text("token ID")
+                                .desired_width(ui.spacing().text_edit_width * 0.3)
+                                .ui(ui);
+                            ui.add(DragValue::new(bias).range(-100.0..=100.0).speed(0.1));
+                            if ui.button("\u{E28F}").on_hover_text("Remove item").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                    }
+
+                    if ui.button("\u{E13D}").on_hover_text("Add item").clicked() {
+                        edited.push((String::new(), 0.0));
+                    }
+                });
+
+                if let Some(remove) = remove {
+                    edited.remove(remove);
+                }
+
+                if edited != entries {
+                    *value = serde_json::to_string(&IndexMap::<String, f64>::from_iter(edited))
+                        .unwrap_or_default();
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn render_parameters(
+    ui: &mut Ui,
+    parameters: &mut Vec<(String, String)>,
+    schema: &[ParameterSpec],
+) {
+    let mut remove = None;
+    let mut has_extra = false;
+
+    for (index, (key, value)) in parameters.iter_mut().enumerate() {
+        if let Some(spec) = schema.iter().find(|spec| spec.key == key.as_str()) {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(spec.key).on_hover_text(spec.description);
+
+                if spec.kind.is_valid(value) {
+                    spec.kind.render(ui, value);
+                } else {
+                    TextEdit::singleline(value)
+                        .hint_text("value")
+                        .desired_width(ui.spacing().text_edit_width * 0.45)
+                        .ui(ui);
+                    ui.label(RichText::new("\u{E193}").color(ui.visuals().warn_fg_color))
+                        .on_hover_text("This value can't be parsed as the type expected by this parameter, so it will be sent to the server as-is.");
+                }
+
+                if ui.button("\u{E28F}").on_hover_text("Remove item").clicked() {
+                    remove = Some(index);
+                }
+            });
+        } else {
+            has_extra = true;
+        }
+    }
+
+    let unused: Vec<&ParameterSpec> = schema
+        .iter()
+        .filter(|spec| !parameters.iter().any(|(key, _)| key == spec.key))
+        .collect();
+
+    if !unused.is_empty() {
+        let mut selected = None;
+
+        ComboBox::from_id_salt(ui.next_auto_id())
+            .selected_text("Add parameter")
+            .show_ui(ui, |ui| {
+                for spec in unused {
+                    if ui
+                        .selectable_label(false, spec.key)
+                        .on_hover_text(spec.description)
+                        .clicked()
+                    {
+                        selected = Some(spec);
+                    }
+                }
+            });
+
+        if let Some(spec) = selected {
+            parameters.push((spec.key.to_string(), spec.default.to_string()));
+        }
+    }
+
+    CollapsingHeader::new("Additional parameters")
+        .id_salt(ui.next_auto_id())
+        .default_open(has_extra)
+        .show(ui, |ui| {
+            ui.label("Values are parsed as JSON where possible and sent as strings otherwise.");
+
+            let key_width = ui.spacing().text_edit_width * 0.55;
+            let value_width = ui.spacing().text_edit_width * 0.45;
+
+            for (index, (key, value)) in parameters.iter_mut().enumerate() {
+                if schema.iter().any(|spec| spec.key == key.as_str()) {
+                    continue;
+                }
+
+                ui.horizontal_wrapped(|ui| {
+                    TextEdit::singleline(key)
+                        .hint_text("key")
+                        .desired_width(key_width)
+                        .ui(ui);
+                    TextEdit::singleline(value)
+                        .hint_text("JSON value")
+                        .desired_width(value_width)
+                        .ui(ui);
+                    if ui.button("\u{E28F}").on_hover_text("Remove item").clicked() {
+                        remove = Some(index);
+                    }
+
+                    if !key.is_empty() {
+                        let suggestion = schema
+                            .iter()
+                            .map(|spec| {
+                                (
+                                    spec.key,
+                                    edit_similarity(key.as_bytes(), spec.key.as_bytes(), 0.0),
+                                )
+                            })
+                            .filter(|(_, similarity)| *similarity >= 0.7)
+                            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+                        let warning = if let Some((suggestion, _)) = suggestion {
+                            format!("Unknown parameter; did you mean \"{suggestion}\"? It will still be sent to the server, which may ignore it.")
+                        } else {
+                            "Unknown parameter. It will still be sent to the server, which may ignore it.".to_string()
+                        };
+
+                        ui.label(RichText::new("\u{E193}").color(ui.visuals().warn_fg_color))
+                            .on_hover_text(warning);
+                    }
+                });
+            }
+
+            if ui.button("\u{E13D}").on_hover_text("Add item").clicked() {
+                parameters.push((String::new(), String::new()));
+            }
+        })
+        .header_response
+        .on_hover_text("Server-specific parameters which aren't covered by the editor above.");
+
+    if let Some(remove) = remove {
+        parameters.remove(remove);
+    }
+}
+
+// Words are usually tokenized differently when preceded by a space, so both forms of a phrase are biased
+pub(super) fn phrase_variants(phrase: &str) -> Vec<String> {
+    if phrase.starts_with(char::is_whitespace) {
+        vec![phrase.to_string()]
+    } else {
+        vec![phrase.to_string(), format!(" {phrase}")]
+    }
+}
+
+pub(super) fn render_phrase_biases(
+    ui: &mut Ui,
+    biases: &mut Vec<(String, f64)>,
+    tokens: impl Fn(&str) -> Option<Vec<u64>>,
+) {
+    let mut remove = None;
+
+    for (index, (phrase, bias)) in biases.iter_mut().enumerate() {
+        ui.horizontal_wrapped(|ui| {
+            TextEdit::singleline(phrase)
+                .hint_text("phrase")
+                .desired_width(ui.spacing().text_edit_width * 0.55)
+                .ui(ui);
+            ui.add(DragValue::new(bias).range(-100.0..=100.0).speed(0.1))
+                .on_hover_text(
+                    "Bias added to the first token of the phrase, from -100 (ban) to 100 (force)",
+                );
+            if ui.button("\u{E28F}").on_hover_text("Remove item").clicked() {
+                remove = Some(index);
+            }
+        });
+
+        if !phrase.is_empty() {
+            if let Some(tokens) = tokens(phrase) {
+                ui.weak(format!("Biased token IDs: {tokens:?}"));
+            } else {
+                ui.weak("Token IDs will be resolved when the next request is sent");
+            }
+        }
+    }
+
+    if let Some(remove) = remove {
+        biases.remove(remove);
+    }
+
+    if ui.button("\u{E13D}").on_hover_text("Add phrase").clicked() {
+        biases.push((String::new(), -100.0));
+    }
+}
@@ -0,0 +1,112 @@
+use tapestry_weave::{
+    ulid::Ulid,
+    v0::{InnerNodeContent, MetadataMap},
+};
+
+use super::ScoredNode;
+
+// Probabilities are stored rounded to four decimal places
+const MINIMUM_PROBABILITY: f64 = 0.00005;
+
+pub fn score(
+    thread: Vec<(Ulid, Vec<u8>)>,
+    tokens: Vec<(Vec<u8>, MetadataMap)>,
+) -> Result<Vec<ScoredNode>, anyhow::Error> {
+    let prompt: Vec<u8> = thread
+        .iter()
+        .flat_map(|(_, bytes)| bytes.iter().copied())
+        .collect();
+    let echoed: Vec<u8> = tokens
+        .iter()
+        .flat_map(|(token, _)| token.iter().copied())
+        .collect();
+
+    // Some endpoints prepend special tokens (such as BOS) to the echoed prompt
+    let mut offset = 0;
+    let mut start = None;
+
+    for (index, (token, _)) in tokens.iter().enumerate() {
+        if echoed[offset..].starts_with(&prompt) {
+            start = Some(index);
+            break;
+        }
+        offset += token.len();
+    }
+
+    let start = match start {
+        Some(start) => start,
+        None => {
+            return Err(anyhow::Error::msg(
+                "Scored text does not match the requested thread",
+            ));
+        }
+    };
+
+    let mut outputs: Vec<_> = thread
+        .into_iter()
+        .map(|(id, bytes)| (id, Vec::new(), Vec::new(), bytes.len()))
+        .collect();
+
+    let mut node_index = 0;
+
+    'outer: for (mut token, metadata) in tokens.into_iter().skip(start) {
+        let logprob = metadata
+            .get("probability")
+            .and_then(|probability| probability.parse::<f64>().ok())
+            .filter(|probability| !probability.is_nan())
+            .map(|probability| probability.max(MINIMUM_PROBABILITY).ln());
+        let token_length = token.len();
+        let mut is_first_piece = true;
+
+        while !token.is_empty() {
+            while outputs
+                .get(node_index)
+                .map(|output| output.3 == 0)
+                .unwrap_or(false)
+            {
+                node_index += 1;
+            }
+
+            let output = match outputs.get_mut(node_index) {
+                Some(output) => output,
+                None => break 'outer,
+            };
+
+            let remainder = if token.len() > output.3 {
+                token.split_off(output.3)
+            } else {
+                Vec::new()
+            };
+
+            let mut piece_metadata = metadata.clone();
+
+            if token.len() != token_length {
+                piece_metadata.shift_remove("token_id");
+            }
+
+            if is_first_piece && let Some(logprob) = logprob {
+                output.2.push(logprob);
+            }
+
+            output.3 -= token.len();
+            output.1.push((token, piece_metadata));
+
+            token = remainder;
+            is_first_piece = false;
+        }
+    }
+
+    Ok(outputs
+        .into_iter()
+        .filter(|(_, tokens, _, remaining)| !tokens.is_empty() && *remaining == 0)
+        .map(|(id, tokens, logprobs, _)| ScoredNode {
+            id,
+            content: InnerNodeContent::Tokens(tokens),
+            perplexity: if logprobs.is_empty() {
+                None
+            } else {
+                Some((-logprobs.iter().sum::<f64>() / logprobs.len() as f64).exp())
+            },
+        })
+        .collect())
+}
@@ -0,0 +1,462 @@
+use std::{borrow::Cow, fmt::Debug, fs, path::PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use eframe::egui::{
+    Button, Context, Key as InputKey, Modal, RichText, Sides, TextEdit, Ui, Widget,
+};
+use parking_lot::RwLock;
+use poll_promise::Promise;
+use tapestry_weave::universal_weave::indexmap::IndexMap;
+
+const MAGIC: &[u8] = b"tapestry-loom-secrets-v1";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+struct SecretStore {
+    secrets: IndexMap<String, String>,
+    key: [u8; 32],
+    salt: [u8; SALT_LENGTH],
+}
+
+static STORE: RwLock<Option<SecretStore>> = RwLock::new(None);
+
+fn store_path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|path| path.join("tapestry-loom").join("secrets.bin"))
+}
+
+fn store_exists() -> bool {
+    store_path().is_some_and(|path| path.exists())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], anyhow::Error> {
+    let mut key = [0; 32];
+
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| anyhow::Error::msg(format!("Key derivation failed: {error}")))?;
+
+    Ok(key)
+}
+
+fn unlock(passphrase: String) -> Result<(), anyhow::Error> {
+    let path =
+        store_path().ok_or_else(|| anyhow::Error::msg("Unable to find configuration directory"))?;
+    let data = fs::read(path)?;
+
+    let data = data
+        .strip_prefix(MAGIC)
+        .filter(|data| data.len() > SALT_LENGTH + NONCE_LENGTH)
+        .ok_or_else(|| anyhow::Error::msg("Secret store is corrupted"))?;
+    let (salt, data) = data.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+
+    let key = derive_key(&passphrase, salt)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::Error::msg("Incorrect passphrase"))?;
+
+    *STORE.write() = Some(SecretStore {
+        secrets: serde_json::from_slice(&plaintext)?,
+        key,
+        salt: salt.try_into()?,
+    });
+
+    Ok(())
+}
+
+fn create(passphrase: String) -> Result<(), anyhow::Error> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+
+    let store = SecretStore {
+        secrets: IndexMap::new(),
+        key: derive_key(&passphrase, &salt)?,
+        salt,
+    };
+
+    store.save()?;
+    *STORE.write() = Some(store);
+
+    Ok(())
+}
+
+type PendingUnlock = Promise<Result<(), anyhow::Error>>;
+
+// Key derivation takes long enough to freeze the UI, so unlocking and creating the store is done on a separate thread
+fn spawn_unlock(
+    task: impl FnOnce() -> Result<(), anyhow::Error> + Send + 'static,
+) -> PendingUnlock {
+    Promise::spawn_thread("secret-store", task)
+}
+
+// Returns the error message of a finished unlock (or None if it succeeded), clearing the pending unlock
+fn poll_unlock(pending: &mut Option<PendingUnlock>) -> Option<Option<String>> {
+    let result = pending
+        .as_ref()?
+        .ready()?
+        .as_ref()
+        .err()
+        .map(|error| format!("{error:#}"));
+    *pending = None;
+
+    Some(result)
+}
+
+// Applies a change to the unlocked store and saves it, only holding the lock while doing so
+fn update(change: impl FnOnce(&mut IndexMap<String, String>)) -> Result<(), anyhow::Error> {
+    let mut guard = STORE.write();
+    let store = guard
+        .as_mut()
+        .ok_or_else(|| anyhow::Error::msg("Secret store is locked"))?;
+
+    change(&mut store.secrets);
+    store.save()
+}
+
+impl SecretStore {
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let path = store_path()
+            .ok_or_else(|| anyhow::Error::msg("Unable to find configuration directory"))?;
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .encrypt(&nonce, serde_json::to_vec(&self.secrets)?.as_slice())
+            .map_err(|_| anyhow::Error::msg("Unable to encrypt secrets"))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = path.with_extension("bin.tmp");
+        fs::write(
+            &temporary,
+            [MAGIC, &self.salt, nonce.as_slice(), &ciphertext].concat(),
+        )?;
+        fs::rename(temporary, path)?;
+
+        Ok(())
+    }
+}
+
+// Replaces ${NAME} references with the matching secret, falling back to environment variables
+pub(super) fn resolve(value: &str) -> Result<Cow<'_, str>, anyhow::Error> {
+    if !value.contains("${") {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    let store = STORE.read();
+    let mut output = String::with_capacity(value.len());
+    let mut remaining = value;
+
+    while let Some(start) = remaining.find("${") {
+        output.push_str(&remaining[..start]);
+
+        let reference = &remaining[start + 2..];
+        let end = reference
+            .find('}')
+            .ok_or_else(|| anyhow::Error::msg("Unterminated secret reference"))?;
+        let name = &reference[..end];
+
+        if let Some(secret) = store.as_ref().and_then(|store| store.secrets.get(name)) {
+            output.push_str(secret);
+        } else if let Ok(variable) = std::env::var(name) {
+            output.push_str(&variable);
+        } else if store.is_none() && store_exists() {
+            return Err(anyhow::Error::msg(format!(
+                "Secret \"{name}\" is unavailable because the secret store is locked"
+            )));
+        } else {
+            return Err(anyhow::Error::msg(format!(
+                "Undefined secret or environment variable \"{name}\""
+            )));
+        }
+
+        remaining = &reference[end + 1..];
+    }
+
+    output.push_str(remaining);
+
+    Ok(Cow::Owned(output))
+}
+
+pub(super) fn is_unlocked() -> bool {
+    STORE.read().is_some()
+}
+
+// Adds a secret to the unlocked store under the given name (with a numeric suffix if the name is taken by a different value), returning a ${NAME} reference to it
+pub(super) fn insert(name: &str, value: &str) -> Result<Option<String>, anyhow::Error> {
+    let mut guard = STORE.write();
+
+    if let Some(store) = guard.as_mut() {
+        let mut candidate = name.to_string();
+        let mut index = 1;
+
+        while store
+            .secrets
+            .get(&candidate)
+            .is_some_and(|existing| existing != value)
+        {
+            index += 1;
+            candidate = format!("{name}_{index}");
+        }
+
+        store.secrets.insert(candidate.clone(), value.to_string());
+
+        if let Err(error) = store.save() {
+            store.secrets.shift_remove(&candidate);
+            return Err(error);
+        }
+
+        Ok(Some(format!("${{{candidate}}}")))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(super) fn is_sensitive_header(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+
+    key == "authorization" || key.contains("key") || key.contains("token") || key.contains("secret")
+}
+
+#[derive(Default)]
+pub(super) struct SecretEditor {
+    passphrase: String,
+    confirmation: String,
+    name: String,
+    value: String,
+    error: Option<String>,
+    pending: Option<PendingUnlock>,
+}
+
+impl Debug for SecretEditor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretEditor").finish_non_exhaustive()
+    }
+}
+
+impl SecretEditor {
+    pub(super) fn render(&mut self, ui: &mut Ui) {
+        if let Some(error) = poll_unlock(&mut self.pending) {
+            self.error = error;
+        }
+
+        let unlocked = STORE.read().is_some();
+
+        if unlocked {
+            self.render_secrets(ui);
+        } else {
+            self.render_unlock(ui);
+        }
+
+        if let Some(error) = &self.error {
+            ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+        }
+    }
+    fn render_secrets(&mut self, ui: &mut Ui) {
+        let names: Vec<String> = STORE
+            .read()
+            .as_ref()
+            .map(|store| store.secrets.keys().cloned().collect())
+            .unwrap_or_default();
+        let mut remove = None;
+        let mut add = false;
+
+        for name in &names {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(RichText::new(format!("${{{name}}}")).monospace())
+                    .on_hover_text("Use this reference in a request header value (e.g. \"Bearer ${NAME}\") to insert the secret when requests are sent.");
+                ui.label("••••••••");
+                if ui.button("\u{E28F}").on_hover_text("Remove secret").clicked() {
+                    remove = Some(name.clone());
+                }
+            });
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            TextEdit::singleline(&mut self.name)
+                .hint_text("Name (e.g. OPENAI_API_KEY)")
+                .desired_width(ui.spacing().text_edit_width * 0.9)
+                .ui(ui);
+            TextEdit::singleline(&mut self.value)
+                .hint_text("Value")
+                .password(true)
+                .desired_width(ui.spacing().text_edit_width * 1.1)
+                .ui(ui);
+            add = ui
+                .add_enabled(
+                    !self.name.is_empty() && !self.name.contains(['{', '}']),
+                    Button::new("\u{E13D}"),
+                )
+                .on_hover_text("Add secret")
+                .clicked();
+        });
+
+        if let Some(name) = remove {
+            self.error = update(|secrets| {
+                secrets.shift_remove(&name);
+            })
+            .err()
+            .map(|error| format!("{error:#}"));
+        }
+
+        if add {
+            let name = std::mem::take(&mut self.name);
+            let value = std::mem::take(&mut self.value);
+
+            self.error = update(|secrets| {
+                secrets.insert(name, value);
+            })
+            .err()
+            .map(|error| format!("{error:#}"));
+        }
+
+        if ui
+            .button("Lock secrets")
+            .on_hover_text("Removes the decrypted secrets from memory. Requests using them will fail until the store is unlocked again.")
+            .clicked()
+        {
+            *STORE.write() = None;
+        }
+    }
+    fn render_unlock(&mut self, ui: &mut Ui) {
+        let exists = store_exists();
+        let busy = self.pending.is_some();
+
+        ui.horizontal_wrapped(|ui| {
+            let response = TextEdit::singleline(&mut self.passphrase)
+                .hint_text("Passphrase")
+                .password(true)
+                .ui(ui);
+
+            let submitted =
+                response.lost_focus() && ui.input(|input| input.key_pressed(InputKey::Enter));
+
+            if exists {
+                if (ui.add_enabled(!busy, Button::new("Unlock secrets")).clicked() || submitted)
+                    && !busy
+                {
+                    let passphrase = std::mem::take(&mut self.passphrase);
+                    self.pending = Some(spawn_unlock(move || unlock(passphrase)));
+                }
+            } else {
+                TextEdit::singleline(&mut self.confirmation)
+                    .hint_text("Confirm passphrase")
+                    .password(true)
+                    .ui(ui);
+
+                if ui
+                    .add_enabled(
+                        !busy && !self.passphrase.is_empty() && self.passphrase == self.confirmation,
+                        Button::new("Create secret store"),
+                    )
+                    .on_hover_text("Creates a passphrase-encrypted file in the configuration directory for storing API keys outside of the settings.")
+                    .clicked()
+                {
+                    let passphrase = std::mem::take(&mut self.passphrase);
+                    self.confirmation.clear();
+                    self.pending = Some(spawn_unlock(move || create(passphrase)));
+                }
+            }
+
+            if busy {
+                ui.spinner();
+            }
+        });
+    }
+}
+
+pub struct SecretPrompt {
+    visible: bool,
+    passphrase: String,
+    error: Option<String>,
+    pending: Option<PendingUnlock>,
+}
+
+impl Default for SecretPrompt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecretPrompt {
+    pub fn new() -> Self {
+        Self {
+            visible: store_exists(),
+            passphrase: String::new(),
+            error: None,
+            pending: None,
+        }
+    }
+    pub fn display(&mut self, ctx: &Context) {
+        if !self.visible {
+            return;
+        }
+
+        let mut unlocked = false;
+
+        if let Some(error) = poll_unlock(&mut self.pending) {
+            unlocked = error.is_none();
+            self.error = error;
+        }
+
+        let busy = self.pending.is_some();
+
+        if Modal::new("secret-store-unlock-modal".into())
+            .show(ctx, |ui| {
+                ui.set_width(300.0);
+                ui.heading("Unlock secrets");
+                ui.label("Enter your passphrase to unlock the API keys used by your models. Requests referencing stored secrets will fail until they are unlocked.");
+                ui.add_space(ui.style().spacing.menu_spacing);
+
+                let response = TextEdit::singleline(&mut self.passphrase)
+                    .hint_text("Passphrase")
+                    .password(true)
+                    .desired_width(f32::INFINITY)
+                    .ui(ui);
+
+                if ui.memory(|memory| memory.focused().is_none()) {
+                    response.request_focus();
+                }
+
+                let submitted =
+                    response.lost_focus() && ui.input(|input| input.key_pressed(InputKey::Enter));
+
+                if let Some(error) = &self.error {
+                    ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+                }
+
+                ui.add_space(ui.style().spacing.menu_spacing);
+                Sides::new().show(
+                    ui,
+                    |_ui| {},
+                    |ui| {
+                        if unlocked {
+                            ui.close();
+                        }
+                        if (ui.add_enabled(!busy, Button::new("Unlock")).clicked() || submitted)
+                            && !busy
+                        {
+                            let passphrase = std::mem::take(&mut self.passphrase);
+                            self.pending = Some(spawn_unlock(move || unlock(passphrase)));
+                        }
+                        if ui.button("Skip").clicked() {
+                            ui.close();
+                        }
+                        if busy {
+                            ui.spinner();
+                        }
+                    },
+                );
+            })
+            .should_close()
+        {
+            self.visible = false;
+            self.passphrase.clear();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use tapestry_weave::ulid::Ulid;
+
+use super::{Passage, SearchResult};
+
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let mut dot = 0.0;
+    let mut a_norm = 0.0;
+    let mut b_norm = 0.0;
+
+    for (a, b) in a.iter().zip(b.iter()) {
+        dot += a * b;
+        a_norm += a * a;
+        b_norm += b * b;
+    }
+
+    if a_norm == 0.0 || b_norm == 0.0 {
+        0.0
+    } else {
+        dot / (a_norm.sqrt() * b_norm.sqrt())
+    }
+}
+
+pub fn rank(query: &[f32], passages: Vec<(Passage, Vec<f32>)>, limit: usize) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = passages
+        .into_iter()
+        .map(|(passage, embedding)| SearchResult {
+            similarity: similarity(query, &embedding),
+            passage,
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    results.truncate(limit);
+
+    results
+}
+
+pub fn cluster(embeddings: Vec<(Ulid, Vec<f32>)>, threshold: f32) -> Vec<Vec<Ulid>> {
+    let similarities: Vec<Vec<f32>> = embeddings
+        .iter()
+        .map(|(_, row)| {
+            embeddings
+                .iter()
+                .map(|(_, column)| similarity(row, column))
+                .collect()
+        })
+        .collect();
+
+    agglomerate(&similarities, threshold)
+        .into_iter()
+        .map(|cluster| {
+            cluster
+                .into_iter()
+                .map(|index| embeddings[index].0)
+                .collect()
+        })
+        .collect()
+}
+
+pub fn cluster_by_edit_distance(items: Vec<(Ulid, Vec<u8>)>, threshold: f32) -> Vec<Vec<Ulid>> {
+    let normalized: Vec<Vec<u8>> = items
+        .iter()
+        .map(|(_, content)| normalize(content))
+        .collect();
+
+    let mut similarities = vec![vec![1.0; items.len()]; items.len()];
+
+    for a in 0..normalized.len() {
+        for b in (a + 1)..normalized.len() {
+            let similarity = edit_similarity(&normalized[a], &normalized[b], threshold);
+            similarities[a][b] = similarity;
+            similarities[b][a] = similarity;
+        }
+    }
+
+    agglomerate(&similarities, threshold)
+        .into_iter()
+        .map(|cluster| cluster.into_iter().map(|index| items[index].0).collect())
+        .collect()
+}
+
+// Collapses runs of whitespace, so that differences in spacing alone aren't counted as edits
+fn normalize(content: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(content.len());
+
+    for word in content
+        .split(|byte| byte.is_ascii_whitespace())
+        .filter(|word| !word.is_empty())
+    {
+        if !output.is_empty() {
+            output.push(b' ');
+        }
+        output.extend_from_slice(word);
+    }
+
+    output
+}
+
+// Returns one minus the Levenshtein distance divided by the length of the longer input
+pub(super) fn edit_similarity(a: &[u8], b: &[u8], threshold: f32) -> f32 {
+    let length = a.len().max(b.len());
+
+    if length == 0 {
+        return 1.0;
+    }
+
+    // The length difference is a lower bound on the edit distance, so the similarity can't exceed this bound
+    let bound = 1.0 - (a.len().abs_diff(b.len()) as f32 / length as f32);
+
+    if bound < threshold {
+        return bound;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_byte) in a.iter().enumerate() {
+        current[0] = i + 1;
+
+        for (j, b_byte) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_byte != b_byte);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    1.0 - (previous[b.len()] as f32 / length as f32)
+}
+
+// Average-linkage agglomerative clustering; clusters and their members retain the input order
+fn agglomerate(similarities: &[Vec<f32>], threshold: f32) -> Vec<Vec<usize>> {
+    let mut clusters: Vec<Vec<usize>> = (0..similarities.len()).map(|index| vec![index]).collect();
+
+    loop {
+        let mut best: Option<(f32, usize, usize)> = None;
+
+        for a in 0..clusters.len() {
+            for b in (a + 1)..clusters.len() {
+                let mut total = 0.0;
+
+                for i in &clusters[a] {
+                    for j in &clusters[b] {
+                        total += similarities[*i][*j];
+                    }
+                }
+
+                let average = total / (clusters[a].len() * clusters[b].len()) as f32;
+
+                if average >= threshold && best.map(|(best, _, _)| average > best).unwrap_or(true) {
+                    best = Some((average, a, b));
+                }
+            }
+        }
+
+        match best {
+            Some((_, a, b)) => {
+                let merged = clusters.remove(b);
+                clusters[a].extend(merged);
+                clusters[a].sort_unstable();
+            }
+            None => break,
+        }
+    }
+
+    clusters.sort_unstable_by_key(|cluster| cluster[0]);
+
+    clusters
+}
@@ -1,5 +1,15 @@
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
 use log::trace;
-use reqwest::Response;
+use reqwest::{
+    Method, Response, StatusCode, Url,
+    header::{HeaderMap, HeaderName, HeaderValue},
+};
 use serde_json::{Map, Value};
 use tapestry_weave::{
     ulid::Ulid,
@@ -7,8 +17,9 @@ use tapestry_weave::{
 };
 
 use super::{
-    EndpointResponse,
+    EndpointResponse, InferenceClient,
     polyparser::{self, LogprobToken, Token},
+    secrets::{self, is_sensitive_header},
 };
 
 pub(super) fn build_json_list(list: &mut Vec<Value>, items: Vec<String>) {
@@ -48,6 +59,185 @@ pub(super) async fn error_for_status(response: Response) -> Result<Response, any
     }
 }
 
+fn status_error(status: StatusCode, text: String) -> Option<anyhow::Error> {
+    if status.is_client_error() || status.is_server_error() {
+        Some(anyhow::Error::msg(format!(
+            "HTTP {}: {}",
+            status.as_u16(),
+            text
+        )))
+    } else if status.is_redirection() || status.is_informational() {
+        Some(anyhow::Error::msg(format!(
+            "Unexpected HTTP status: {}",
+            status
+        )))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestCapture {
+    pub endpoint: String,
+    header_names: Vec<String>,
+    pub request: Value,
+    pub response: Value,
+    pub started: SystemTime,
+    pub duration: Duration,
+    options: ResponseOptions,
+}
+
+// A failed request, along with the capture of the request and whatever the endpoint responded with
+#[derive(Debug)]
+pub struct CapturedError {
+    pub capture: Arc<RequestCapture>,
+    error: anyhow::Error,
+}
+
+impl Display for CapturedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for CapturedError {}
+
+impl RequestCapture {
+    // Header values frequently contain credentials, so only the names are kept
+    pub fn redacted_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.header_names
+            .iter()
+            .map(|key| (key.as_str(), "[redacted]"))
+    }
+    pub(super) fn model(&self) -> Ulid {
+        self.options.model
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ResponseOptions {
+    pub(super) metadata: Vec<(String, String)>,
+    pub(super) tokenization_identifier: Ulid,
+    pub(super) echo: bool,
+    pub(super) single_token: bool,
+    pub(super) requested_top: Option<usize>,
+    pub(super) model: Ulid,
+}
+
+pub(super) fn build_headers(headers: &[(String, String)]) -> Result<HeaderMap, anyhow::Error> {
+    let mut header_map = HeaderMap::with_capacity(headers.len());
+
+    for (key, value) in headers {
+        let resolved = secrets::resolve(value)?;
+        let mut header = HeaderValue::from_str(&resolved)?;
+        header.set_sensitive(matches!(resolved, Cow::Owned(_)) || is_sensitive_header(key));
+
+        header_map.insert(HeaderName::from_bytes(key.as_bytes())?, header);
+    }
+
+    Ok(header_map)
+}
+
+pub(super) async fn send_request(
+    client: &InferenceClient,
+    endpoint: &str,
+    headers: &[(String, String)],
+    body: Map<String, Value>,
+    options: ResponseOptions,
+    capture: bool,
+) -> Result<Vec<EndpointResponse>, anyhow::Error> {
+    let request = Value::Object(body);
+
+    let started = SystemTime::now();
+    let timer = Instant::now();
+
+    let response = client
+        .client
+        .request(Method::POST, Url::parse(endpoint)?)
+        .headers(build_headers(headers)?)
+        .json(&request)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let text = response.text().await?;
+    let parsed: Result<Map<String, Value>, serde_json::Error> = serde_json::from_str(&text);
+
+    // The raw body text is kept if the response isn't a JSON object, as it usually explains the failure
+    let capture = if capture {
+        Some(Arc::new(RequestCapture {
+            endpoint: endpoint.to_string(),
+            header_names: headers.iter().map(|(key, _)| key.clone()).collect(),
+            request,
+            response: match &parsed {
+                Ok(response) => Value::Object(response.clone()),
+                Err(_) => Value::String(text.clone()),
+            },
+            started,
+            duration: timer.elapsed(),
+            options: options.clone(),
+        }))
+    } else {
+        None
+    };
+
+    let result = match status_error(status, text) {
+        Some(error) => Err(error),
+        None => match parsed {
+            Ok(response) => {
+                let responses = parse_response(
+                    response,
+                    options.metadata,
+                    options.tokenization_identifier,
+                    options.echo,
+                    options.single_token,
+                    options.requested_top,
+                );
+
+                if responses.is_empty() {
+                    Err(response_schema_error())
+                } else {
+                    Ok(responses)
+                }
+            }
+            Err(error) => Err(error.into()),
+        },
+    };
+
+    match (result, capture) {
+        (Ok(mut responses), capture) => {
+            for response in &mut responses {
+                response.capture = capture.clone();
+            }
+
+            Ok(responses)
+        }
+        (Err(error), Some(capture)) => Err(CapturedError { capture, error }.into()),
+        (Err(error), None) => Err(error),
+    }
+}
+
+// Sends a previously captured request body as-is; only the headers are taken from the current model settings
+pub(super) async fn replay_request(
+    client: &InferenceClient,
+    headers: &[(String, String)],
+    capture: &RequestCapture,
+) -> Result<Vec<EndpointResponse>, anyhow::Error> {
+    if let Value::Object(body) = &capture.request {
+        send_request(
+            client,
+            &capture.endpoint,
+            headers,
+            body.clone(),
+            capture.options.clone(),
+            true,
+        )
+        .await
+    } else {
+        Err(anyhow::Error::msg("Captured request body is not an object"))
+    }
+}
+
 pub(super) fn parse_response(
     response: Map<String, Value>,
     metadata: Vec<(String, String)>,
@@ -98,6 +288,7 @@ pub(super) fn parse_response(
                 root: echo,
                 content: InnerNodeContent::Snippet(text),
                 metadata,
+                capture: None,
             }),
             polyparser::ResponseContents::Tokens(tokens) => {
                 let calculate_base_token_metadata = |token: &Token| {
@@ -184,6 +375,7 @@ pub(super) fn parse_response(
                                 token_metadata,
                             )]),
                             metadata: metadata.clone(),
+                            capture: None,
                         }
                     }));
 
@@ -257,12 +449,14 @@ pub(super) fn parse_response(
                     root: echo,
                     content: InnerNodeContent::Tokens(tokens),
                     metadata,
+                    capture: None,
                 });
             }
             polyparser::ResponseContents::Empty => outputs.push(EndpointResponse {
                 root: echo,
                 content: InnerNodeContent::Snippet(Vec::new()),
                 metadata,
+                capture: None,
             }),
         };
     }
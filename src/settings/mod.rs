@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use eframe::egui::{Layout, OpenUrl, Sides};
 
 use crate::settings::{
-    inference::InferenceSettings,
+    inference::{InferenceCache, InferenceSettings},
     notices::EligibleNotices,
     shortcuts::{KeyboardShortcuts, Shortcuts},
 };
@@ -419,7 +419,7 @@ impl DocumentSettings {
 }
 
 impl Settings {
-    pub fn render(&mut self, ui: &mut Ui) {
+    pub fn render(&mut self, ui: &mut Ui, cache: &InferenceCache) {
         ScrollArea::both()
             .auto_shrink(false)
             .animated(false)
@@ -463,7 +463,7 @@ impl Settings {
 
                         ui.separator();
                         ui.heading("Inference");
-                        self.inference.render(ui);
+                        self.inference.render(ui, cache);
                         ui.separator();
                         ui.heading("Document");
                         self.documents.render(ui);
@@ -0,0 +1,73 @@
+use std::{convert::Infallible, sync::Arc};
+
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Status},
+    options,
+    request::{FromRequest, Outcome},
+};
+
+use crate::registry::Registry;
+
+// The API key sent by the client as a bearer token, if any
+pub struct ApiKey(Option<String>);
+
+impl ApiKey {
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ApiKey(
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|header| header.strip_prefix("Bearer "))
+                .map(|key| key.trim().to_string()),
+        ))
+    }
+}
+
+// Adds CORS headers to responses for requests from origins listed in models.toml
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Some(origin) = request.headers().get_one("Origin")
+            && let Some(registry) = request.rocket().state::<Arc<Registry>>()
+            && registry.allows_origin(origin)
+        {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Origin",
+                origin.to_string(),
+            ));
+            response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST"));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type",
+            ));
+            response.set_header(Header::new("Access-Control-Max-Age", "86400"));
+            response.set_header(Header::new("Vary", "Origin"));
+        }
+    }
+}
+
+// Browsers send preflight requests without credentials, so these are answered for every path (the CORS headers are added by the fairing)
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
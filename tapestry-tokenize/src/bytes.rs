@@ -0,0 +1,225 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use tokenizers::{DecoderWrapper, Model, Tokenizer};
+
+// The GPT-2 style mapping between raw bytes and the printable characters used by byte-level vocabularies
+static BYTE_CHARS: LazyLock<[char; 256]> = LazyLock::new(|| {
+    let mut chars = ['\0'; 256];
+    let mut offset = 0;
+
+    for byte in 0..=255u8 {
+        chars[byte as usize] = if matches!(byte, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF) {
+            byte as char
+        } else {
+            offset += 1;
+            char::from_u32(255 + offset).unwrap()
+        };
+    }
+
+    chars
+});
+
+static CHAR_BYTES: LazyLock<HashMap<char, u8>> = LazyLock::new(|| {
+    BYTE_CHARS
+        .iter()
+        .enumerate()
+        .map(|(byte, char)| (*char, byte as u8))
+        .collect()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    ByteLevel,
+    Metaspace(char),
+    Other,
+}
+
+impl TokenFormat {
+    pub fn detect(tokenizer: &Tokenizer) -> Self {
+        match tokenizer.get_decoder() {
+            Some(decoder) => Self::from_decoder(decoder),
+            None => Self::Other,
+        }
+    }
+    fn from_decoder(decoder: &DecoderWrapper) -> Self {
+        match decoder {
+            DecoderWrapper::ByteLevel(_) => Self::ByteLevel,
+            DecoderWrapper::Metaspace(metaspace) => Self::Metaspace(metaspace.get_replacement()),
+            // SentencePiece-style tokenizers converted by transformers replace "▁" with spaces
+            DecoderWrapper::Replace(_) => Self::Metaspace('▁'),
+            DecoderWrapper::Sequence(sequence) => sequence
+                .get_decoders()
+                .iter()
+                .map(Self::from_decoder)
+                .find(|format| *format != Self::Other)
+                .unwrap_or(Self::Other),
+            _ => Self::Other,
+        }
+    }
+}
+
+// Encodes raw bytes using the printable characters of a byte-level vocabulary
+pub fn byte_level_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| BYTE_CHARS[*byte as usize])
+        .collect()
+}
+
+fn parse_byte_token(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+
+    if hex.len() == 2 {
+        u8::from_str_radix(hex, 16).ok()
+    } else {
+        None
+    }
+}
+
+// Returns the exact bytes represented by a single token, which may not be valid UTF-8 on its own
+pub fn token_bytes(tokenizer: &Tokenizer, format: TokenFormat, id: u32) -> Option<Vec<u8>> {
+    let token = tokenizer.id_to_token(id)?;
+
+    match format {
+        TokenFormat::ByteLevel => Some(
+            token
+                .chars()
+                .map(|char| CHAR_BYTES.get(&char).copied())
+                .collect::<Option<Vec<u8>>>()
+                .unwrap_or_else(|| token.into_bytes()),
+        ),
+        TokenFormat::Metaspace(replacement) => Some(match parse_byte_token(&token) {
+            Some(byte) => vec![byte],
+            None => token.replace(replacement, " ").into_bytes(),
+        }),
+        TokenFormat::Other => match parse_byte_token(&token) {
+            Some(byte) => Some(vec![byte]),
+            None => tokenizer
+                .decode(&[id], false)
+                .ok()
+                .map(|text| text.into_bytes()),
+        },
+    }
+}
+
+fn model_ids(tokenizer: &Tokenizer, input: &str) -> Result<Vec<u32>, anyhow::Error> {
+    Ok(tokenizer
+        .get_model()
+        .tokenize(input)
+        .map_err(anyhow::Error::from_boxed)?
+        .into_iter()
+        .map(|token| token.id)
+        .collect())
+}
+
+// Tokenizes arbitrary bytes without replacing invalid UTF-8, so that the resulting tokens decode to exactly the same bytes
+pub fn encode_bytes(
+    tokenizer: &Tokenizer,
+    format: TokenFormat,
+    data: &[u8],
+) -> Result<Vec<u32>, anyhow::Error> {
+    if let Ok(input) = str::from_utf8(data) {
+        return Ok(tokenizer
+            .encode_fast(input, false)
+            .map_err(anyhow::Error::from_boxed)?
+            .get_ids()
+            .to_vec());
+    }
+
+    let mut ids = Vec::new();
+
+    for (index, chunk) in data.utf8_chunks().enumerate() {
+        let valid = chunk.valid();
+
+        if !valid.is_empty() {
+            match format {
+                // Encoding the chunk as a separate input would prepend another word boundary, so the model is used directly
+                TokenFormat::Metaspace(replacement) if index > 0 => {
+                    ids.extend(model_ids(
+                        tokenizer,
+                        &valid.replace(' ', &replacement.to_string()),
+                    )?);
+                }
+                _ => ids.extend(
+                    tokenizer
+                        .encode_fast(valid, false)
+                        .map_err(anyhow::Error::from_boxed)?
+                        .get_ids(),
+                ),
+            }
+        }
+
+        let invalid = chunk.invalid();
+
+        if !invalid.is_empty() {
+            match format {
+                TokenFormat::ByteLevel => {
+                    ids.extend(model_ids(tokenizer, &byte_level_encode(invalid))?);
+                }
+                TokenFormat::Metaspace(_) => {
+                    for byte in invalid {
+                        ids.push(tokenizer.token_to_id(&format!("<0x{byte:02X}>")).ok_or_else(
+                            || {
+                                anyhow::Error::msg(
+                                    "Tokenizer does not have byte fallback tokens, so it cannot represent invalid UTF-8",
+                                )
+                            },
+                        )?);
+                    }
+                }
+                TokenFormat::Other => {
+                    return Err(anyhow::Error::msg(
+                        "Tokenizer is not byte-level, so it cannot represent invalid UTF-8",
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+// Detokenizes tokens into bytes, preserving partial UTF-8 characters which would otherwise be replaced during decoding
+pub fn decode_bytes(
+    tokenizer: &Tokenizer,
+    format: TokenFormat,
+    ids: &[u32],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let decoded = tokenizer
+        .decode(ids, true)
+        .map_err(anyhow::Error::from_boxed)?;
+
+    if format == TokenFormat::Other || !decoded.contains(char::REPLACEMENT_CHARACTER) {
+        return Ok(decoded.into_bytes());
+    }
+
+    let added_vocabulary = tokenizer.get_added_vocabulary();
+    let mut bytes = Vec::new();
+
+    for id in ids {
+        let token = tokenizer
+            .id_to_token(*id)
+            .ok_or_else(|| anyhow::Error::msg(format!("Unknown token ID {id}")))?;
+
+        if !added_vocabulary.is_special_token(&token) {
+            bytes.extend(
+                token_bytes(tokenizer, format, *id)
+                    .ok_or_else(|| anyhow::Error::msg(format!("Unknown token ID {id}")))?,
+            );
+        }
+    }
+
+    if str::from_utf8(&bytes).is_ok() {
+        return Ok(decoded.into_bytes());
+    }
+
+    // Metaspace decoders remove the word boundary prepended to the start of the input
+    if matches!(format, TokenFormat::Metaspace(_))
+        && bytes.first() == Some(&b' ')
+        && !decoded.starts_with(' ')
+    {
+        bytes.remove(0);
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokenizers::Tokenizer;
+
+pub mod sentencepiece;
+pub mod tiktoken;
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerKind {
+    #[default]
+    HuggingFace,
+    SentencePiece,
+    Tiktoken,
+}
+
+impl TokenizerKind {
+    // Formats other than tokenizer.json are converted into an equivalent tokenizer.json before being loaded
+    pub fn load(
+        &self,
+        data: Vec<u8>,
+        pattern: Option<&str>,
+        special_tokens: &HashMap<String, u32>,
+    ) -> Result<(Tokenizer, Vec<u8>), anyhow::Error> {
+        let converted = match self {
+            Self::HuggingFace => data,
+            Self::SentencePiece => sentencepiece::convert(&data)?,
+            Self::Tiktoken => tiktoken::convert(&data, pattern, special_tokens)?,
+        };
+
+        let tokenizer = Tokenizer::from_bytes(&converted).map_err(anyhow::Error::from_boxed)?;
+
+        Ok((tokenizer, converted))
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::HuggingFace => "huggingface",
+            Self::SentencePiece => "sentencepiece",
+            Self::Tiktoken => "tiktoken",
+        }
+    }
+}
+
+fn added_token(id: u32, content: &str, special: bool) -> Value {
+    json!({
+        "id": id,
+        "content": content,
+        "single_word": false,
+        "lstrip": false,
+        "rstrip": false,
+        "normalized": false,
+        "special": special,
+    })
+}
+
+fn tokenizer_json(
+    added_tokens: Vec<Value>,
+    normalizer: Value,
+    pre_tokenizer: Value,
+    decoder: Value,
+    model: Value,
+) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(serde_json::to_vec(&json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": added_tokens,
+        "normalizer": normalizer,
+        "pre_tokenizer": pre_tokenizer,
+        "post_processor": null,
+        "decoder": decoder,
+        "model": model,
+    }))?)
+}
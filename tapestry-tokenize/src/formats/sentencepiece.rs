@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+use super::{added_token, tokenizer_json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PieceType {
+    Normal,
+    Unknown,
+    Control,
+    UserDefined,
+    Unused,
+    Byte,
+}
+
+struct Piece {
+    piece: String,
+    score: f32,
+    kind: PieceType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelType {
+    Unigram,
+    Bpe,
+    Word,
+    Char,
+}
+
+struct ModelProto {
+    pieces: Vec<Piece>,
+    model_type: ModelType,
+    byte_fallback: bool,
+    normalizer: String,
+    add_dummy_prefix: bool,
+}
+
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+// A minimal protobuf reader, supporting only what is needed to read a SentencePiece ModelProto
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+    fn varint(&mut self) -> Result<u64, anyhow::Error> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| anyhow::Error::msg("Unexpected end of SentencePiece model"))?;
+            self.position += 1;
+
+            value |= u64::from(byte & 0x7F) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(anyhow::Error::msg("Invalid varint in SentencePiece model"))
+    }
+    fn take(&mut self, length: usize) -> Result<&'a [u8], anyhow::Error> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow::Error::msg("Unexpected end of SentencePiece model"))?;
+        let data = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(data)
+    }
+    fn field(&mut self) -> Result<Option<(u64, WireValue<'a>)>, anyhow::Error> {
+        if self.position >= self.data.len() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+
+        let value = match key & 0x7 {
+            0 => WireValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                WireValue::Fixed64
+            }
+            2 => {
+                let length = usize::try_from(self.varint()?)?;
+                WireValue::Bytes(self.take(length)?)
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into()?)),
+            wire_type => {
+                return Err(anyhow::Error::msg(format!(
+                    "Unsupported protobuf wire type {wire_type} in SentencePiece model"
+                )));
+            }
+        };
+
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+fn parse_piece(data: &[u8]) -> Result<Piece, anyhow::Error> {
+    let mut reader = Reader::new(data);
+    let mut piece = Piece {
+        piece: String::new(),
+        score: 0.0,
+        kind: PieceType::Normal,
+    };
+
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, WireValue::Bytes(bytes)) => piece.piece = String::from_utf8(bytes.to_vec())?,
+            (2, WireValue::Fixed32(bits)) => piece.score = f32::from_bits(bits),
+            (3, WireValue::Varint(kind)) => {
+                piece.kind = match kind {
+                    2 => PieceType::Unknown,
+                    3 => PieceType::Control,
+                    4 => PieceType::UserDefined,
+                    5 => PieceType::Unused,
+                    6 => PieceType::Byte,
+                    _ => PieceType::Normal,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(piece)
+}
+
+fn parse_model(data: &[u8]) -> Result<ModelProto, anyhow::Error> {
+    let mut reader = Reader::new(data);
+    let mut model = ModelProto {
+        pieces: Vec::new(),
+        model_type: ModelType::Unigram,
+        byte_fallback: false,
+        normalizer: String::new(),
+        add_dummy_prefix: true,
+    };
+
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, WireValue::Bytes(bytes)) => model.pieces.push(parse_piece(bytes)?),
+            (2, WireValue::Bytes(bytes)) => {
+                let mut trainer = Reader::new(bytes);
+
+                while let Some((field, value)) = trainer.field()? {
+                    match (field, value) {
+                        (3, WireValue::Varint(model_type)) => {
+                            model.model_type = match model_type {
+                                2 => ModelType::Bpe,
+                                3 => ModelType::Word,
+                                4 => ModelType::Char,
+                                _ => ModelType::Unigram,
+                            }
+                        }
+                        (35, WireValue::Varint(byte_fallback)) => {
+                            model.byte_fallback = byte_fallback != 0
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (3, WireValue::Bytes(bytes)) => {
+                let mut normalizer = Reader::new(bytes);
+
+                while let Some((field, value)) = normalizer.field()? {
+                    match (field, value) {
+                        (1, WireValue::Bytes(name)) => {
+                            model.normalizer = String::from_utf8_lossy(name).to_string()
+                        }
+                        (3, WireValue::Varint(add_dummy_prefix)) => {
+                            model.add_dummy_prefix = add_dummy_prefix != 0
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if model.pieces.is_empty() {
+        return Err(anyhow::Error::msg("SentencePiece model contains no pieces"));
+    }
+
+    Ok(model)
+}
+
+// Derives BPE merges from the vocabulary, ordered by the merged piece (SentencePiece stores pieces in order of their score)
+fn extract_merges(pieces: &[Piece]) -> Vec<(String, String)> {
+    let ids: HashMap<&str, usize> = pieces
+        .iter()
+        .enumerate()
+        .map(|(id, piece)| (piece.piece.as_str(), id))
+        .collect();
+
+    let mut merges = Vec::new();
+
+    for (id, piece) in pieces.iter().enumerate() {
+        if piece.kind != PieceType::Normal {
+            continue;
+        }
+
+        for (index, _) in piece.piece.char_indices().skip(1) {
+            let (left, right) = piece.piece.split_at(index);
+
+            if let (Some(left_id), Some(right_id)) = (ids.get(left), ids.get(right)) {
+                merges.push((id, *left_id, *right_id, left, right));
+            }
+        }
+    }
+
+    merges.sort_unstable_by_key(|(id, left_id, right_id, _, _)| (*id, *left_id, *right_id));
+
+    merges
+        .into_iter()
+        .map(|(_, _, _, left, right)| (left.to_string(), right.to_string()))
+        .collect()
+}
+
+// Converts a SentencePiece tokenizer.model file into an equivalent tokenizer.json file
+pub fn convert(data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let model = parse_model(data)?;
+
+    let unk_id = model
+        .pieces
+        .iter()
+        .position(|piece| piece.kind == PieceType::Unknown);
+
+    let added_tokens = model
+        .pieces
+        .iter()
+        .enumerate()
+        .filter(|(_, piece)| matches!(piece.kind, PieceType::Control | PieceType::UserDefined))
+        .map(|(id, piece)| added_token(id as u32, &piece.piece, piece.kind == PieceType::Control))
+        .collect();
+
+    let normalizer = if model.normalizer.contains("nfkc") {
+        json!({"type": "NFKC"})
+    } else {
+        Value::Null
+    };
+
+    let prepend_scheme = if model.add_dummy_prefix {
+        "first"
+    } else {
+        "never"
+    };
+
+    let split = model.model_type != ModelType::Bpe;
+
+    let metaspace = json!({
+        "type": "Metaspace",
+        "replacement": "▁",
+        "prepend_scheme": prepend_scheme,
+        "split": split,
+    });
+
+    let decoder = json!({
+        "type": "Sequence",
+        "decoders": [{"type": "ByteFallback"}, metaspace],
+    });
+
+    let tokenizer_model = match model.model_type {
+        ModelType::Unigram => json!({
+            "type": "Unigram",
+            "unk_id": unk_id,
+            "vocab": model
+                .pieces
+                .iter()
+                .map(|piece| json!([piece.piece, piece.score]))
+                .collect::<Vec<_>>(),
+            "byte_fallback": model.byte_fallback,
+        }),
+        ModelType::Bpe => json!({
+            "type": "BPE",
+            "dropout": null,
+            "unk_token": unk_id.map(|id| model.pieces[id].piece.clone()),
+            "continuing_subword_prefix": null,
+            "end_of_word_suffix": null,
+            "fuse_unk": true,
+            "byte_fallback": model.byte_fallback,
+            "ignore_merges": false,
+            "vocab": model
+                .pieces
+                .iter()
+                .enumerate()
+                .map(|(id, piece)| (piece.piece.clone(), Value::from(id)))
+                .collect::<Map<_, _>>(),
+            "merges": extract_merges(&model.pieces),
+        }),
+        ModelType::Word | ModelType::Char => {
+            return Err(anyhow::Error::msg(
+                "Only Unigram and BPE SentencePiece models are supported",
+            ));
+        }
+    };
+
+    let unused = model
+        .pieces
+        .iter()
+        .filter(|piece| piece.kind == PieceType::Unused)
+        .count();
+
+    if unused > 0 {
+        log::info!("Ignoring {unused} unused SentencePiece pieces");
+    }
+
+    tokenizer_json(
+        added_tokens,
+        normalizer,
+        metaspace,
+        decoder,
+        tokenizer_model,
+    )
+}
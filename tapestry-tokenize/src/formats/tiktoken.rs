@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::{Map, Value, json};
+
+use super::{added_token, tokenizer_json};
+use crate::bytes::byte_level_encode;
+
+// The pre-tokenization pattern used by cl100k_base, which is also used by most tiktoken-based models
+pub const DEFAULT_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+fn parse_ranks(data: &[u8]) -> Result<HashMap<Vec<u8>, u32>, anyhow::Error> {
+    let mut ranks = HashMap::new();
+
+    for (index, line) in str::from_utf8(data)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (token, rank) = line.split_once(' ').ok_or_else(|| {
+            anyhow::Error::msg(format!("Invalid tiktoken rank on line {}", index + 1))
+        })?;
+
+        ranks.insert(STANDARD.decode(token)?, rank.trim().parse()?);
+    }
+
+    if ranks.is_empty() {
+        return Err(anyhow::Error::msg("tiktoken file contains no ranks"));
+    }
+
+    Ok(ranks)
+}
+
+// Runs byte pair encoding using only merges ranked below max_rank, which splits a token into the two parts it was merged from
+fn bpe(ranks: &HashMap<Vec<u8>, u32>, token: &[u8], max_rank: u32) -> Vec<Vec<u8>> {
+    let mut parts: Vec<Vec<u8>> = token.iter().map(|byte| vec![*byte]).collect();
+
+    loop {
+        let mut best: Option<(usize, u32)> = None;
+
+        for (index, pair) in parts.windows(2).enumerate() {
+            if let Some(rank) = ranks.get(&[pair[0].as_slice(), pair[1].as_slice()].concat())
+                && *rank < max_rank
+                && best.is_none_or(|(_, best_rank)| *rank < best_rank)
+            {
+                best = Some((index, *rank));
+            }
+        }
+
+        if let Some((index, _)) = best {
+            let right = parts.remove(index + 1);
+            parts[index].extend(right);
+        } else {
+            break;
+        }
+    }
+
+    parts
+}
+
+// Converts a tiktoken rank file into an equivalent byte-level BPE tokenizer.json file
+pub fn convert(
+    data: &[u8],
+    pattern: Option<&str>,
+    special_tokens: &HashMap<String, u32>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let ranks = parse_ranks(data)?;
+
+    let mut merges = Vec::new();
+
+    for (token, rank) in &ranks {
+        if token.len() < 2 {
+            continue;
+        }
+
+        let parts = bpe(&ranks, token, *rank);
+
+        if let [left, right] = parts.as_slice() {
+            merges.push((*rank, byte_level_encode(left), byte_level_encode(right)));
+        } else {
+            return Err(anyhow::Error::msg(format!(
+                "Unable to derive merge for tiktoken rank {rank}"
+            )));
+        }
+    }
+
+    merges.sort_unstable_by_key(|(rank, _, _)| *rank);
+
+    let mut added_tokens: Vec<_> = special_tokens
+        .iter()
+        .map(|(content, id)| added_token(*id, content, true))
+        .collect();
+    added_tokens.sort_unstable_by_key(|token| token["id"].as_u64());
+
+    let pre_tokenizer = json!({
+        "type": "Sequence",
+        "pretokenizers": [
+            {
+                "type": "Split",
+                "pattern": {"Regex": pattern.unwrap_or(DEFAULT_PATTERN)},
+                "behavior": "Isolated",
+                "invert": false,
+            },
+            {
+                "type": "ByteLevel",
+                "add_prefix_space": false,
+                "trim_offsets": true,
+                "use_regex": false,
+            },
+        ],
+    });
+
+    let decoder = json!({
+        "type": "ByteLevel",
+        "add_prefix_space": true,
+        "trim_offsets": true,
+        "use_regex": true,
+    });
+
+    let model = json!({
+        "type": "BPE",
+        "dropout": null,
+        "unk_token": null,
+        "continuing_subword_prefix": null,
+        "end_of_word_suffix": null,
+        "fuse_unk": false,
+        "byte_fallback": false,
+        "ignore_merges": true,
+        // Special tokens are also added to the vocabulary, as added tokens missing from it are assigned new IDs
+        "vocab": ranks
+            .iter()
+            .map(|(token, rank)| (byte_level_encode(token), Value::from(*rank)))
+            .chain(
+                special_tokens
+                    .iter()
+                    .map(|(content, id)| (content.clone(), Value::from(*id))),
+            )
+            .collect::<Map<_, _>>(),
+        "merges": merges
+            .into_iter()
+            .map(|(_, left, right)| [left, right])
+            .collect::<Vec<_>>(),
+    });
+
+    tokenizer_json(added_tokens, Value::Null, pre_tokenizer, decoder, model)
+}
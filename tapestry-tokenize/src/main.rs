@@ -1,26 +1,22 @@
-use std::{collections::HashMap, env, fs, path::PathBuf, sync::Arc};
+use std::{env, fs, path::PathBuf, sync::Arc};
 
+use base64::{Engine, engine::general_purpose::STANDARD};
 use log::{info, warn};
 use rocket::{State, get, http::Status, post, serde::json::Json, tokio::task::block_in_place};
 use serde::{Deserialize, Serialize};
-use tokenizers::Tokenizer;
 
-#[derive(Serialize, Deserialize, Default)]
-struct ModelConfig {
-    #[serde(default)]
-    models: Vec<Model>,
-}
+use crate::{
+    access::{ApiKey, Cors},
+    bytes::{decode_bytes, encode_bytes, token_bytes},
+    registry::{LoadedModel, ModelInfo, Registry, RegistryStatus},
+    template::ChatRequest,
+};
 
-#[derive(Serialize, Deserialize, Default)]
-struct Model {
-    label: String,
-    file: PathBuf,
-}
-
-#[derive(Default)]
-struct SharedState {
-    tokenizers: HashMap<String, (Tokenizer, Arc<[u8]>)>,
-}
+mod access;
+mod bytes;
+mod formats;
+mod registry;
+mod template;
 
 #[rocket::main]
 #[allow(clippy::result_large_err)]
@@ -37,30 +33,28 @@ async fn main() -> Result<(), anyhow::Error> {
         fs::write("models.toml", include_bytes!("default-models.toml"))?;
     }
 
-    let model_config: ModelConfig = toml::from_slice(&fs::read("models.toml")?)?;
-
-    let mut tokenizers = HashMap::with_capacity(model_config.models.len());
-
-    for model in model_config.models {
-        println!("Loading {}", model.file.display());
-        let contents = fs::read(model.file)?;
-
-        tokenizers.insert(
-            model.label,
-            (
-                Tokenizer::from_bytes(&contents).map_err(anyhow::Error::from_boxed)?,
-                contents.into(),
-            ),
-        );
-    }
-
-    let shared = SharedState { tokenizers };
+    let registry = Arc::new(Registry::new(PathBuf::from("models.toml"))?);
+    registry.clone().watch()?;
 
     let _rocket = rocket::build()
-        .manage(shared)
+        .manage(registry)
+        .attach(Cors)
         .mount(
             "/",
-            rocket::routes![tokenize, detokenize, tokenizer, tokenize_root],
+            rocket::routes![
+                health,
+                models,
+                tokenize,
+                tokenize_with_offsets,
+                tokenize_batch,
+                count,
+                detokenize,
+                decode_tokens,
+                apply_chat_template,
+                tokenizer,
+                tokenize_root,
+                access::preflight
+            ],
         )
         .launch()
         .await?;
@@ -68,78 +62,331 @@ async fn main() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Tokenizers are loaded on first use, so this should only be called from a blocking context
+fn get_model(
+    state: &Registry,
+    model: &str,
+    key: &ApiKey,
+) -> Result<Arc<LoadedModel>, (Status, String)> {
+    // Authorization is checked before the model is looked up, so that unauthorized clients cannot determine which models exist
+    if !state.authorize(model, key.as_deref()) {
+        warn!("Rejected unauthorized request for {:?}", model);
+
+        return Err((
+            Status::Unauthorized,
+            format!("A valid API key is required to use model {model:?}"),
+        ));
+    }
+
+    match state.get(model) {
+        Some(Ok(loaded)) => Ok(loaded),
+        Some(Err(error)) => {
+            warn!("Unable to load model {:?}: {error:#}", model);
+
+            Err((
+                Status::InternalServerError,
+                format!("Unable to load model {model:?}: {error:#}"),
+            ))
+        }
+        None => {
+            warn!("Unable to find model {:?}", model);
+
+            Err((Status::NotFound, format!("Unable to find model {model:?}")))
+        }
+    }
+}
+
+#[get("/health")]
+async fn health(state: &State<Arc<Registry>>) -> Json<RegistryStatus> {
+    Json(state.status())
+}
+
+#[get("/models")]
+async fn models(state: &State<Arc<Registry>>, key: ApiKey) -> Json<Vec<ModelInfo>> {
+    Json(state.list(key.as_deref()))
+}
+
 #[post("/<model>", data = "<data>")]
 async fn tokenize_root(
-    state: &State<SharedState>,
+    state: &State<Arc<Registry>>,
     model: &str,
+    key: ApiKey,
     data: Vec<u8>,
-) -> Result<Json<Vec<u32>>, Status> {
-    tokenize(state, model, data).await
+) -> Result<Json<Vec<u32>>, (Status, String)> {
+    tokenize(state, model, key, data).await
 }
 
 #[post("/<model>/tokenize", data = "<data>")]
 async fn tokenize(
-    state: &State<SharedState>,
+    state: &State<Arc<Registry>>,
     model: &str,
+    key: ApiKey,
     data: Vec<u8>,
-) -> Result<Json<Vec<u32>>, Status> {
-    if let Some((tokenizer, _)) = state.tokenizers.get(model) {
+) -> Result<Json<Vec<u32>>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
         info!("Tokenizing {} bytes using {:?}", data.len(), model);
 
-        block_in_place(|| {
-            let input = if let Ok(input) = str::from_utf8(&data) {
-                input
-            } else {
-                warn!(
-                    "Request body contains characters not supported by the current tokenization backend"
-                );
-                &String::from_utf8_lossy(&data)
-            };
-
-            let encoding = tokenizer
-                .encode_fast(input, false)
-                .map_err(|_| Status::InternalServerError)?;
-
-            Ok(Json(encoding.get_ids().to_vec()))
+        Ok(Json(encode(&loaded, &data)?))
+    })
+}
+
+fn encode(loaded: &LoadedModel, data: &[u8]) -> Result<Vec<u32>, (Status, String)> {
+    encode_bytes(&loaded.tokenizer, loaded.format, data).map_err(|error| {
+        warn!("Unable to tokenize input: {error:#}");
+
+        (Status::UnprocessableEntity, format!("{error:#}"))
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchInput {
+    Text(String),
+    Bytes { base64: String },
+}
+
+fn encode_batch(
+    loaded: &LoadedModel,
+    inputs: Vec<BatchInput>,
+) -> Result<Vec<Vec<u32>>, (Status, String)> {
+    inputs
+        .into_iter()
+        .map(|input| match input {
+            BatchInput::Text(text) => encode(loaded, text.as_bytes()),
+            BatchInput::Bytes { base64 } => {
+                let bytes = STANDARD.decode(base64).map_err(|error| {
+                    (
+                        Status::UnprocessableEntity,
+                        format!("Invalid base64 input: {error}"),
+                    )
+                })?;
+
+                encode(loaded, &bytes)
+            }
         })
-    } else {
-        warn!("Unable to find model {:?}", model);
+        .collect()
+}
 
-        Err(Status::NotFound)
-    }
+#[post("/<model>/tokenize_batch", data = "<data>")]
+async fn tokenize_batch(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+    data: Json<Vec<BatchInput>>,
+) -> Result<Json<Vec<Vec<u32>>>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
+        info!("Tokenizing {} inputs using {:?}", data.0.len(), model);
+
+        Ok(Json(encode_batch(&loaded, data.0)?))
+    })
+}
+
+#[post("/<model>/count", data = "<data>")]
+async fn count(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+    data: Json<Vec<BatchInput>>,
+) -> Result<Json<Vec<usize>>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
+        info!(
+            "Counting tokens in {} inputs using {:?}",
+            data.0.len(),
+            model
+        );
+
+        Ok(Json(
+            encode_batch(&loaded, data.0)?
+                .iter()
+                .map(|ids| ids.len())
+                .collect(),
+        ))
+    })
+}
+
+#[derive(Serialize)]
+struct TokenSpan {
+    id: u32,
+    start: usize,
+    end: usize,
+    bytes: Vec<u8>,
+}
+
+#[post("/<model>/tokenize_with_offsets", data = "<data>")]
+async fn tokenize_with_offsets(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+    data: Vec<u8>,
+) -> Result<Json<Vec<TokenSpan>>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
+        info!(
+            "Tokenizing {} bytes with offsets using {:?}",
+            data.len(),
+            model
+        );
+
+        // Offsets are only available for valid UTF-8, as other inputs are not tokenized as a single string
+        let (ids, offsets) = if let Ok(input) = str::from_utf8(&data) {
+            let encoding = loaded
+                .tokenizer
+                .encode(input, false)
+                .map_err(|error| (Status::InternalServerError, error.to_string()))?;
+
+            (
+                encoding.get_ids().to_vec(),
+                Some(encoding.get_offsets().to_vec()),
+            )
+        } else {
+            (encode(&loaded, &data)?, None)
+        };
+
+        let token_bytes = ids
+            .iter()
+            .map(|id| {
+                token_bytes(&loaded.tokenizer, loaded.format, *id).ok_or((
+                    Status::InternalServerError,
+                    format!("Unable to decode token {id}"),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // When the tokens reproduce the input exactly, their spans can be derived from the token bytes rather than the (potentially trimmed) offsets reported by the tokenizer
+        let concatenated = token_bytes.concat();
+        let prefixed = concatenated.strip_prefix(b" ") == Some(data.as_slice());
+        let exact = offsets.is_none() || prefixed || concatenated == data;
+        let skip = usize::from(prefixed);
+        let mut position = 0;
+
+        Ok(Json(
+            ids.into_iter()
+                .zip(token_bytes)
+                .enumerate()
+                .map(|(index, (id, bytes))| {
+                    let (start, end) = match &offsets {
+                        Some(offsets) if !exact => offsets[index],
+                        _ => {
+                            position += bytes.len();
+                            (
+                                (position - bytes.len()).saturating_sub(skip),
+                                position.saturating_sub(skip),
+                            )
+                        }
+                    };
+
+                    TokenSpan {
+                        id,
+                        start,
+                        end,
+                        bytes,
+                    }
+                })
+                .collect(),
+        ))
+    })
 }
 
 #[get("/<model>/tokenizer.json")]
-async fn tokenizer(state: &State<SharedState>, model: &str) -> Result<Arc<[u8]>, Status> {
-    if let Some((_, data)) = state.tokenizers.get(model) {
-        info!("Sending tokenizer file for {:?}", model);
+async fn tokenizer(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+) -> Result<Arc<[u8]>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
 
-        Ok(data.clone())
-    } else {
-        warn!("Unable to find model {:?}", model);
+        info!("Sending tokenizer file for {:?}", model);
 
-        Err(Status::NotFound)
-    }
+        Ok(loaded.file.clone())
+    })
 }
 
 #[post("/<model>/detokenize", data = "<data>")]
 async fn detokenize(
-    state: &State<SharedState>,
+    state: &State<Arc<Registry>>,
     model: &str,
+    key: ApiKey,
     data: Json<Vec<u32>>,
-) -> Result<Vec<u8>, Status> {
-    if let Some((tokenizer, _)) = state.tokenizers.get(model) {
+) -> Result<Vec<u8>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
         info!("Detokenizing {} tokens using {:?}", data.0.len(), model);
 
-        block_in_place(|| {
-            Ok(tokenizer
-                .decode(&data.0, true)
-                .map_err(|_| Status::InternalServerError)?
-                .into_bytes())
+        decode_bytes(&loaded.tokenizer, loaded.format, &data.0).map_err(|error| {
+            (
+                Status::UnprocessableEntity,
+                format!("Unable to detokenize input: {error:#}"),
+            )
         })
-    } else {
-        warn!("Unable to find model {:?}", model);
+    })
+}
 
-        Err(Status::NotFound)
-    }
+#[post("/<model>/decode_tokens", data = "<data>")]
+async fn decode_tokens(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+    data: Json<Vec<u32>>,
+) -> Result<Json<Vec<Vec<u8>>>, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
+        info!("Decoding {} tokens using {:?}", data.0.len(), model);
+
+        Ok(Json(
+            data.0
+                .iter()
+                .map(|id| {
+                    token_bytes(&loaded.tokenizer, loaded.format, *id).ok_or((
+                        Status::UnprocessableEntity,
+                        format!("Token {id} is not in the model's vocabulary"),
+                    ))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ))
+    })
+}
+
+#[post("/<model>/apply_chat_template", data = "<data>")]
+async fn apply_chat_template(
+    state: &State<Arc<Registry>>,
+    model: &str,
+    key: ApiKey,
+    data: Json<ChatRequest>,
+) -> Result<String, (Status, String)> {
+    block_in_place(|| {
+        let loaded = get_model(state, model, &key)?;
+
+        if let Some(template) = &loaded.chat_template {
+            info!(
+                "Applying chat template to {} messages using {:?}",
+                data.0.messages.len(),
+                model
+            );
+
+            template.render(&data.0).map_err(|error| {
+                warn!("Unable to apply chat template: {error:#}");
+
+                (Status::UnprocessableEntity, format!("{error:#}"))
+            })
+        } else {
+            warn!("Model {:?} does not have a chat template", model);
+
+            Err((
+                Status::NotImplemented,
+                format!(
+                    "Model {model:?} does not have a chat template. Add a tokenizer_config value to the model's entry in models.toml."
+                ),
+            ))
+        }
+    })
 }
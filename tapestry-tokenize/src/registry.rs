@@ -0,0 +1,455 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock, mpsc},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokenizers::Tokenizer;
+
+use crate::{bytes::TokenFormat, formats::TokenizerKind, template::ChatTemplate};
+
+#[derive(Serialize, Deserialize, Default)]
+struct ModelConfig {
+    #[serde(default)]
+    models: Vec<Model>,
+    #[serde(default)]
+    models_dir: Option<PathBuf>,
+    #[serde(default)]
+    max_loaded: Option<usize>,
+    #[serde(default)]
+    api_keys: Vec<String>,
+    #[serde(default)]
+    cors_origins: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+struct Model {
+    label: String,
+    file: PathBuf,
+    #[serde(default)]
+    kind: TokenizerKind,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    special_tokens: HashMap<String, u32>,
+    #[serde(default)]
+    tokenizer_config: Option<PathBuf>,
+    #[serde(default)]
+    api_keys: Option<Vec<String>>,
+}
+
+pub struct LoadedModel {
+    pub tokenizer: Tokenizer,
+    pub format: TokenFormat,
+    pub file: Arc<[u8]>,
+    pub chat_template: Option<ChatTemplate>,
+}
+
+impl LoadedModel {
+    fn load(model: &Model) -> Result<Self, anyhow::Error> {
+        info!("Loading {} ({})", model.file.display(), model.kind.name());
+        let contents = fs::read(&model.file)?;
+
+        // The converted tokenizer.json is kept for models using other formats, so that it can be sent to clients
+        let (tokenizer, file) =
+            model
+                .kind
+                .load(contents, model.pattern.as_deref(), &model.special_tokens)?;
+
+        let chat_template = match &model.tokenizer_config {
+            Some(path) => {
+                info!("Loading {}", path.display());
+                Some(ChatTemplate::load(path)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            format: TokenFormat::detect(&tokenizer),
+            tokenizer,
+            file: file.into(),
+            chat_template,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct ModelInfo {
+    label: String,
+    kind: TokenizerKind,
+    chat_template: bool,
+    loaded: bool,
+}
+
+#[derive(Serialize)]
+pub struct RegistryStatus {
+    models: usize,
+    loaded: usize,
+}
+
+#[derive(Default)]
+struct Models {
+    models: HashMap<String, Model>,
+    models_dir: Option<PathBuf>,
+    max_loaded: Option<usize>,
+    api_keys: Vec<String>,
+    cors_origins: Vec<String>,
+}
+
+impl Models {
+    // Models with their own list of API keys only accept those keys, while all other models accept the global list of API keys (if one is set)
+    fn authorize(&self, label: &str, key: Option<&str>) -> bool {
+        let allowed = match self.models.get(label) {
+            Some(model) => match &model.api_keys {
+                Some(api_keys) => api_keys,
+                None if self.api_keys.is_empty() => return true,
+                None => &self.api_keys,
+            },
+            // Unknown models are rejected in the same way as protected models, so that their existence can't be determined by requesting them
+            None if self.requires_keys() => return false,
+            None => return true,
+        };
+
+        // Every key is compared in constant time, so that response timing doesn't reveal how much of a key matched
+        key.is_some_and(|key| {
+            allowed.iter().fold(false, |found, allowed| {
+                found | bool::from(key.as_bytes().ct_eq(allowed.as_bytes()))
+            })
+        })
+    }
+    fn requires_keys(&self) -> bool {
+        !self.api_keys.is_empty() || self.models.values().any(|model| model.api_keys.is_some())
+    }
+}
+
+// Failed loads are cached for this long, so that repeated requests for a broken model don't reload it every time
+const FAILED_LOAD_RETRY: Duration = Duration::from_secs(30);
+
+type LoadResult = Result<Arc<LoadedModel>, (String, Instant)>;
+
+// Concurrent requests for a model which is still loading wait on the same slot rather than loading the model again
+type LoadSlot = Arc<OnceLock<LoadResult>>;
+
+#[derive(Default)]
+struct LoadedModels {
+    models: HashMap<String, (LoadSlot, u64)>,
+    clock: u64,
+}
+
+impl LoadedModels {
+    fn is_loaded(&self, label: &str) -> bool {
+        self.models
+            .get(label)
+            .is_some_and(|(slot, _)| matches!(slot.get(), Some(Ok(_))))
+    }
+    fn evict(&mut self, limit: Option<usize>) {
+        if let Some(limit) = limit {
+            while self.models.len() > limit.max(1) {
+                // Models which are still loading are never unloaded, as their load would be repeated by the next request
+                if let Some(label) = self
+                    .models
+                    .iter()
+                    .filter(|(_, (slot, _))| slot.get().is_some())
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(label, _)| label.clone())
+                {
+                    info!("Unloading {:?}", label);
+                    self.models.remove(&label);
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub struct Registry {
+    config: PathBuf,
+    models: RwLock<Arc<Models>>,
+    loaded: Mutex<LoadedModels>,
+}
+
+impl Registry {
+    pub fn new(config: PathBuf) -> Result<Self, anyhow::Error> {
+        let models = read_config(&config)?;
+
+        println!("Registered {} models", models.models.len());
+
+        Ok(Self {
+            config,
+            models: RwLock::new(Arc::new(models)),
+            loaded: Mutex::new(LoadedModels::default()),
+        })
+    }
+    fn models(&self) -> Arc<Models> {
+        self.models.read().unwrap().clone()
+    }
+    // Returns None if the model does not exist, loading the model's tokenizer if it is not already loaded
+    pub fn get(&self, label: &str) -> Option<Result<Arc<LoadedModel>, anyhow::Error>> {
+        let models = self.models();
+        let model = models.models.get(label)?;
+
+        let slot = {
+            let mut loaded = self.loaded.lock().unwrap();
+            loaded.clock += 1;
+            let clock = loaded.clock;
+
+            let (slot, last_used) = loaded
+                .models
+                .entry(label.to_string())
+                .or_insert_with(|| (LoadSlot::default(), clock));
+            *last_used = clock;
+
+            if let Some(Err((_, failed))) = slot.get()
+                && failed.elapsed() >= FAILED_LOAD_RETRY
+            {
+                *slot = LoadSlot::default();
+            }
+
+            slot.clone()
+        };
+
+        // Tokenizers are loaded without holding the lock, so that slow loads do not block requests for other models
+        let mut initialized = false;
+        let result = slot.get_or_init(|| {
+            initialized = true;
+
+            LoadedModel::load(model)
+                .map(Arc::new)
+                .map_err(|error| (format!("{error:#}"), Instant::now()))
+        });
+
+        if initialized {
+            self.loaded.lock().unwrap().evict(models.max_loaded);
+        }
+
+        Some(match result {
+            Ok(model) => Ok(model.clone()),
+            Err((error, _)) => Err(anyhow::Error::msg(error.clone())),
+        })
+    }
+    pub fn authorize(&self, label: &str, key: Option<&str>) -> bool {
+        self.models().authorize(label, key)
+    }
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.models()
+            .cors_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/') == origin)
+    }
+    // Only models which can be used with the specified API key are listed
+    pub fn list(&self, key: Option<&str>) -> Vec<ModelInfo> {
+        let models = self.models();
+        let loaded = self.loaded.lock().unwrap();
+
+        let mut list: Vec<_> = models
+            .models
+            .values()
+            .filter(|model| models.authorize(&model.label, key))
+            .map(|model| ModelInfo {
+                label: model.label.clone(),
+                kind: model.kind,
+                chat_template: model.tokenizer_config.is_some(),
+                loaded: loaded.is_loaded(&model.label),
+            })
+            .collect();
+        list.sort_unstable_by(|a, b| a.label.cmp(&b.label));
+
+        list
+    }
+    pub fn status(&self) -> RegistryStatus {
+        RegistryStatus {
+            models: self.models().models.len(),
+            loaded: {
+                let loaded = self.loaded.lock().unwrap();
+
+                loaded
+                    .models
+                    .keys()
+                    .filter(|label| loaded.is_loaded(label))
+                    .count()
+            },
+        }
+    }
+    fn reload(&self) -> Result<(), anyhow::Error> {
+        let updated = read_config(&self.config)?;
+        let current = self.models();
+
+        if updated.models == current.models
+            && updated.models_dir == current.models_dir
+            && updated.max_loaded == current.max_loaded
+            && updated.api_keys == current.api_keys
+            && updated.cors_origins == current.cors_origins
+        {
+            return Ok(());
+        }
+
+        info!("Reloaded configuration ({} models)", updated.models.len());
+
+        {
+            let mut loaded = self.loaded.lock().unwrap();
+
+            loaded
+                .models
+                .retain(|label, _| current.models.get(label) == updated.models.get(label));
+            loaded.evict(updated.max_loaded);
+        }
+
+        *self.models.write().unwrap() = Arc::new(updated);
+
+        Ok(())
+    }
+    // Watches the configuration file and models directory, reloading the configuration whenever either changes
+    pub fn watch(self: Arc<Self>) -> Result<(), anyhow::Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event
+                    && !matches!(event.kind, EventKind::Access(_))
+                {
+                    let _ = sender.send(());
+                }
+            })?;
+
+        // Editors often replace files rather than modifying them, so the containing directory is watched instead of the file itself
+        let directory = match self.config.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        let mut models_dir = self.models().models_dir.clone();
+        watch_models_dir(&mut watcher, None, models_dir.as_deref());
+
+        thread::spawn(move || {
+            while receiver.recv().is_ok() {
+                // Wait for bursts of changes to finish before reloading
+                thread::sleep(Duration::from_millis(250));
+                while receiver.try_recv().is_ok() {}
+
+                if let Err(error) = self.reload() {
+                    warn!("Unable to reload configuration: {error:#}");
+                    continue;
+                }
+
+                let updated = self.models().models_dir.clone();
+
+                if updated != models_dir {
+                    watch_models_dir(&mut watcher, models_dir.as_deref(), updated.as_deref());
+                    models_dir = updated;
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+fn watch_models_dir(watcher: &mut RecommendedWatcher, old: Option<&Path>, new: Option<&Path>) {
+    if let Some(old) = old {
+        let _ = watcher.unwatch(old);
+    }
+
+    if let Some(new) = new
+        && let Err(error) = watcher.watch(new, RecursiveMode::Recursive)
+    {
+        warn!("Unable to watch {}: {error:#}", new.display());
+    }
+}
+
+fn read_config(path: &Path) -> Result<Models, anyhow::Error> {
+    let config: ModelConfig = toml::from_slice(&fs::read(path)?)?;
+
+    let mut models = HashMap::with_capacity(config.models.len());
+
+    if let Some(directory) = &config.models_dir {
+        for model in scan_directory(directory)? {
+            models.insert(model.label.clone(), model);
+        }
+    }
+
+    // Models specified in models.toml take priority over models found in models_dir
+    for model in config.models {
+        models.insert(model.label.clone(), model);
+    }
+
+    Ok(Models {
+        models,
+        models_dir: config.models_dir,
+        max_loaded: config.max_loaded,
+        api_keys: config.api_keys,
+        cors_origins: config.cors_origins,
+    })
+}
+
+// Registers a model for every subdirectory containing a tokenizer.json file, using the subdirectory's name as the label
+fn scan_directory(directory: &Path) -> Result<Vec<Model>, anyhow::Error> {
+    let mut models = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if !path.is_dir() {
+            continue;
+        }
+
+        // HuggingFace's cache stores each repository in a "models--{owner}--{name}" folder
+        let label = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.strip_prefix("models--").unwrap_or(name).to_string(),
+            None => continue,
+        };
+
+        if let Some(file) = find_newest(&path, "tokenizer.json") {
+            let config = file.with_file_name("tokenizer_config.json");
+
+            models.push(Model {
+                label,
+                tokenizer_config: config.is_file().then_some(config),
+                file,
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(models)
+}
+
+// Finds the most recently modified file with the specified name, such as the tokenizer from the latest snapshot of a HuggingFace cache folder
+fn find_newest(directory: &Path, name: &str) -> Option<PathBuf> {
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                directories.push(path);
+            } else if path.file_name().is_some_and(|file_name| file_name == name) && path.is_file()
+            {
+                let modified = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+
+                if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                    newest = Some((modified, path));
+                }
+            }
+        }
+    }
+
+    newest.map(|(_, path)| path)
+}
@@ -0,0 +1,182 @@
+use std::{fmt::Write, fs, path::Path};
+
+use chrono::Local;
+use minijinja::{Environment, Error, ErrorKind, context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const TEMPLATE_NAME: &str = "chat_template";
+
+#[derive(Deserialize)]
+struct TokenizerConfig {
+    #[serde(default)]
+    chat_template: Option<TemplateSource>,
+    #[serde(default)]
+    bos_token: Option<SpecialToken>,
+    #[serde(default)]
+    eos_token: Option<SpecialToken>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TemplateSource {
+    Single(String),
+    Named(Vec<NamedTemplate>),
+}
+
+#[derive(Deserialize)]
+struct NamedTemplate {
+    name: String,
+    template: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpecialToken {
+    Content(String),
+    AddedToken { content: String },
+}
+
+impl SpecialToken {
+    fn into_content(self) -> String {
+        match self {
+            Self::Content(content) => content,
+            Self::AddedToken { content } => content,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub add_generation_prompt: bool,
+    #[serde(default)]
+    pub continue_final_message: bool,
+    #[serde(default)]
+    pub tools: Option<Value>,
+    #[serde(default)]
+    pub documents: Option<Value>,
+    #[serde(default)]
+    pub strip_bos_token: bool,
+}
+
+pub struct ChatTemplate {
+    environment: Environment<'static>,
+    bos_token: String,
+    eos_token: String,
+}
+
+impl ChatTemplate {
+    // Loads the chat template from a tokenizer_config.json file, as used by HuggingFace transformers
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let config: TokenizerConfig = serde_json::from_slice(&fs::read(path)?)?;
+
+        let source = match config.chat_template {
+            Some(TemplateSource::Single(template)) => template,
+            Some(TemplateSource::Named(templates)) => templates
+                .into_iter()
+                .find(|template| template.name == "default")
+                .map(|template| template.template)
+                .ok_or_else(|| {
+                    anyhow::Error::msg(
+                        "tokenizer_config.json does not contain a default chat template",
+                    )
+                })?,
+            None => {
+                return Err(anyhow::Error::msg(
+                    "tokenizer_config.json does not contain a chat template",
+                ));
+            }
+        };
+
+        let mut environment = Environment::new();
+
+        // These match the Jinja options used by transformers when rendering chat templates
+        environment.set_trim_blocks(true);
+        environment.set_lstrip_blocks(true);
+        environment
+            .set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+        environment.add_function("raise_exception", |message: String| -> Result<(), Error> {
+            Err(Error::new(ErrorKind::InvalidOperation, message))
+        });
+        // Templates use this to insert the current date, usually in the system prompt
+        environment.add_function("strftime_now", |format: String| -> Result<String, Error> {
+            let mut output = String::new();
+
+            write!(output, "{}", Local::now().format(&format)).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Invalid strftime format {format:?}"),
+                )
+            })?;
+
+            Ok(output)
+        });
+        environment.add_template_owned(TEMPLATE_NAME, source)?;
+
+        Ok(Self {
+            environment,
+            bos_token: config
+                .bos_token
+                .map(SpecialToken::into_content)
+                .unwrap_or_default(),
+            eos_token: config
+                .eos_token
+                .map(SpecialToken::into_content)
+                .unwrap_or_default(),
+        })
+    }
+    pub fn render(&self, request: &ChatRequest) -> Result<String, anyhow::Error> {
+        if request.add_generation_prompt && request.continue_final_message {
+            return Err(anyhow::Error::msg(
+                "add_generation_prompt and continue_final_message cannot be used together",
+            ));
+        }
+
+        let mut rendered = self
+            .environment
+            .get_template(TEMPLATE_NAME)?
+            .render(context! {
+                messages => request.messages,
+                add_generation_prompt => request.add_generation_prompt,
+                tools => request.tools,
+                documents => request.documents,
+                bos_token => self.bos_token,
+                eos_token => self.eos_token,
+            })?;
+
+        // Completions endpoints usually add their own BOS token, so a BOS token inserted by the template would otherwise be duplicated
+        if request.strip_bos_token
+            && !self.bos_token.is_empty()
+            && let Some(stripped) = rendered.strip_prefix(&self.bos_token)
+        {
+            rendered = stripped.to_string();
+        }
+
+        if request.continue_final_message {
+            // Like transformers, the output is truncated after the final message's content so that the model continues it rather than starting a new turn
+            let content = request
+                .messages
+                .last()
+                .and_then(|message| message.get("content"))
+                .and_then(Value::as_str)
+                .ok_or_else(|| {
+                    anyhow::Error::msg(
+                        "continue_final_message requires a final message with text content",
+                    )
+                })?;
+
+            let end = rendered
+                .rfind(content.trim())
+                .map(|start| start + content.trim().len())
+                .ok_or_else(|| {
+                    anyhow::Error::msg("Unable to find the final message in the rendered template")
+                })?;
+
+            Ok(rendered[..end].to_string())
+        } else {
+            Ok(rendered)
+        }
+    }
+}